@@ -0,0 +1,91 @@
+#[cfg(feature = "tokio")]
+use std::time::SystemTime;
+
+#[cfg(feature = "tokio")]
+use crate::cid::ensure_unchanged;
+use crate::{Cid, CidError, BLOCK_SIZE};
+
+#[cfg(feature = "tokio")]
+impl Cid {
+    /// Like [`Cid::from_reader`], for a tokio [`AsyncRead`](tokio::io::AsyncRead).
+    pub async fn from_async_reader(
+        version: u8,
+        mut reader: impl tokio::io::AsyncRead + Unpin,
+    ) -> Result<Self, CidError> {
+        use tokio::io::AsyncReadExt;
+
+        let mut builder = Self::try_builder(version)?;
+        let mut buf = vec![0; BLOCK_SIZE];
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            builder.update(&buf[..n]);
+        }
+        Ok(builder.finalize())
+    }
+
+    /// Like [`Cid::from_file`], for a tokio [`File`](tokio::fs::File).
+    pub async fn from_async_file(
+        version: u8,
+        file: &mut tokio::fs::File,
+    ) -> Result<(Self, SystemTime), CidError> {
+        let metadata = file.metadata().await?;
+        let cid = Self::from_async_reader(version, &mut *file).await?;
+        let modified = ensure_unchanged(&metadata, &file.metadata().await?, cid.size())?;
+        Ok((cid, modified))
+    }
+}
+
+#[cfg(feature = "futures")]
+impl Cid {
+    /// Like [`Cid::from_reader`], for a [`futures_util::AsyncRead`].
+    pub async fn from_futures_reader(
+        version: u8,
+        mut reader: impl futures_util::AsyncRead + Unpin,
+    ) -> Result<Self, CidError> {
+        use futures_util::AsyncReadExt;
+
+        let mut builder = Self::try_builder(version)?;
+        let mut buf = vec![0; BLOCK_SIZE];
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            builder.update(&buf[..n]);
+        }
+        Ok(builder.finalize())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn tokio_matches_sync() {
+        use std::io::Write;
+
+        let data = crate::test_data(BLOCK_SIZE * 3 + 11);
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&data).unwrap();
+        let mut file = tokio::fs::File::open(file.path()).await.unwrap();
+        let (cid, _) = Cid::from_async_file(Cid::VERSION_RAW, &mut file)
+            .await
+            .unwrap();
+        assert_eq!(cid, Cid::from_data(Cid::VERSION_RAW, &data));
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn futures_matches_sync() {
+        let data = crate::test_data(BLOCK_SIZE * 3 + 11);
+        let cid =
+            futures_executor::block_on(Cid::from_futures_reader(Cid::VERSION_RAW, data.as_slice()))
+                .unwrap();
+        assert_eq!(cid, Cid::from_data(Cid::VERSION_RAW, &data));
+    }
+}
@@ -0,0 +1,224 @@
+use bytes::{Buf, BufMut};
+use bytes_varint::{VarIntSupport, VarIntSupportMut};
+use std::mem;
+use thiserror::Error;
+
+use crate::{hasher::CidHasher, tree::SubtreeStack, Cid, CidBuilder, CidVersion, Hash};
+
+#[derive(Error, Debug)]
+pub enum CheckpointError {
+    #[error("invalid magic")]
+    InvalidMagic,
+
+    #[error("unsupported checkpoint version: {version}")]
+    UnsupportedVersion { version: u8 },
+
+    #[error("unsupported cid version: {version}")]
+    UnsupportedCidVersion { version: u8 },
+
+    #[error("invalid length")]
+    InvalidLength,
+
+    #[error("inconsistent state")]
+    Inconsistent,
+
+    #[error("hasher does not match cid version: {version}")]
+    HasherMismatch { version: u8 },
+}
+
+const MAGIC: [u8; 4] = *b"ANYC";
+
+const VERSION: u8 = 1;
+
+const FLAG_LEAVES: u8 = 1;
+
+/// Saving and restoring builders, so that hashing large inputs can pick up
+/// where it left off.
+///
+/// A checkpoint is encoded as:
+///
+/// - the magic bytes `ANYC` and a format version byte;
+/// - the CID version byte and the number of bytes hashed as a varint;
+/// - a flags byte, whose lowest bit tells whether leaves are kept;
+/// - the roots of the complete subtrees, from the largest to the smallest;
+/// - if leaves are kept, every leaf hash.
+impl<H: CidHasher> CidBuilder<H> {
+    /// Saves the state of the builder, or returns `None` if it is not at a
+    /// block boundary.
+    pub fn checkpoint(&self) -> Option<Vec<u8>> {
+        if self.head != 0 {
+            return None;
+        }
+        let leaves = self.leaves.as_deref().unwrap_or_default();
+        let mut buf = Vec::with_capacity(
+            MAGIC.len() + 13 + mem::size_of::<Hash>() * (self.stack.roots().len() + leaves.len()),
+        );
+        buf.put_slice(&MAGIC);
+        buf.put_u8(VERSION);
+        buf.put_u8(self.version.byte());
+        buf.put_u64_varint(self.size);
+        buf.put_u8(if self.leaves.is_some() {
+            FLAG_LEAVES
+        } else {
+            0
+        });
+        for root in self.stack.roots() {
+            buf.put_slice(root);
+        }
+        for leaf in leaves {
+            buf.put_slice(leaf);
+        }
+        Some(buf)
+    }
+
+    /// Restores a builder saved with [`CidBuilder::checkpoint`], hashing with
+    /// `hasher` from then on, which must use the hash function of the saved
+    /// version.
+    pub fn resume_with_hasher(mut buf: &[u8], hasher: H) -> Result<Self, CheckpointError> {
+        if buf.remaining() < MAGIC.len() + 2 {
+            return Err(CheckpointError::InvalidLength);
+        }
+        if buf[..MAGIC.len()] != MAGIC {
+            return Err(CheckpointError::InvalidMagic);
+        }
+        buf.advance(MAGIC.len());
+        let version = buf.get_u8();
+        if version != VERSION {
+            return Err(CheckpointError::UnsupportedVersion { version });
+        }
+        let version = buf.get_u8();
+        let Some(info) = CidVersion::get(version) else {
+            return Err(CheckpointError::UnsupportedCidVersion { version });
+        };
+        if hasher.algorithm() != info.hash_algorithm() {
+            return Err(CheckpointError::HasherMismatch { version });
+        }
+        let mut builder = Self::with_hasher(version, hasher);
+        let size = buf
+            .try_get_u64_varint()
+            .map_err(|_| CheckpointError::InvalidLength)?;
+        let flags = buf
+            .try_get_u8()
+            .map_err(|_| CheckpointError::InvalidLength)?;
+        if flags & !FLAG_LEAVES != 0 || size > Cid::MAX_SIZE {
+            return Err(CheckpointError::Inconsistent);
+        }
+
+        let block_size = builder.block_size() as u64;
+        if size % block_size != 0 {
+            return Err(CheckpointError::Inconsistent);
+        }
+        let num_leaves = size / block_size;
+        let num_roots = num_leaves.count_ones() as usize;
+        let num_hashes = if flags & FLAG_LEAVES != 0 {
+            usize::try_from(num_leaves)
+                .ok()
+                .and_then(|num_leaves| num_leaves.checked_add(num_roots))
+        } else {
+            Some(num_roots)
+        };
+        let hashes_len = num_hashes.and_then(|num| num.checked_mul(mem::size_of::<Hash>()));
+        if hashes_len != Some(buf.remaining()) {
+            return Err(CheckpointError::InvalidLength);
+        }
+        let mut hashes = buf.chunks_exact(mem::size_of::<Hash>()).map(|hash| {
+            let mut buf = Hash::default();
+            buf.copy_from_slice(hash);
+            buf
+        });
+        let roots = hashes.by_ref().take(num_roots).collect();
+        builder.stack =
+            SubtreeStack::from_roots(num_leaves, roots).ok_or(CheckpointError::Inconsistent)?;
+        if flags & FLAG_LEAVES != 0 {
+            let leaves: Vec<Hash> = hashes.collect();
+            let mut stack = SubtreeStack::default();
+            for leaf in &leaves {
+                stack.push(&builder.tree, *leaf);
+            }
+            if !stack.roots().eq(builder.stack.roots()) {
+                return Err(CheckpointError::Inconsistent);
+            }
+            builder.leaves = Some(leaves);
+        }
+        builder.size = size;
+        Ok(builder)
+    }
+}
+
+impl CidBuilder {
+    /// Restores a builder saved with [`CidBuilder::checkpoint`].
+    pub fn resume(buf: &[u8]) -> Result<Self, CheckpointError> {
+        let version = buf
+            .get(MAGIC.len() + 1)
+            .ok_or(CheckpointError::InvalidLength)?;
+        let hasher = CidVersion::get(*version)
+            .ok_or(CheckpointError::UnsupportedCidVersion { version: *version })?
+            .hash_algorithm()
+            .hasher();
+        Self::resume_with_hasher(buf, hasher)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BLOCK_SIZE;
+
+    #[test]
+    fn resume_continues_hashing() {
+        for version in [Cid::VERSION_RAW, Cid::VERSION_BLAKE3] {
+            let block_size = CidVersion::get(version).unwrap().block_size();
+            let data = crate::test_data(block_size * 7 + 3);
+            let (first, second) = data.split_at(block_size * 5);
+
+            let mut builder = Cid::builder(version);
+            builder.update(&first[..10]);
+            assert!(builder.checkpoint().is_none());
+            builder.update(&first[10..]);
+            let checkpoint = builder.checkpoint().unwrap();
+
+            let mut builder = CidBuilder::resume(&checkpoint).unwrap();
+            builder.update(second);
+            assert_eq!(builder.finalize(), Cid::from_data(version, &data));
+        }
+    }
+
+    #[test]
+    fn resume_keeps_tree() {
+        let data = crate::test_data(BLOCK_SIZE * 6);
+        let mut builder = Cid::builder(Cid::VERSION_RAW).with_tree();
+        builder.update(&data[..BLOCK_SIZE * 3]);
+        let mut checkpoint = builder.checkpoint().unwrap();
+
+        let mut builder = CidBuilder::resume(&checkpoint).unwrap();
+        builder.update(&data[BLOCK_SIZE * 3..]);
+        let (cid, tree) = builder.finalize_with_tree();
+        let tree = tree.unwrap();
+        assert_eq!(cid, Cid::from_data(Cid::VERSION_RAW, &data));
+        assert_eq!(tree.num_leaves(), 6);
+
+        *checkpoint.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            CidBuilder::resume(&checkpoint),
+            Err(CheckpointError::Inconsistent)
+        ));
+        checkpoint.pop();
+        assert!(matches!(
+            CidBuilder::resume(&checkpoint),
+            Err(CheckpointError::InvalidLength)
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized() {
+        let mut checkpoint = MAGIC.to_vec();
+        checkpoint.put_u8(VERSION);
+        checkpoint.put_u8(Cid::VERSION_RAW);
+        checkpoint.put_u64_varint(u64::MAX / BLOCK_SIZE as u64 * BLOCK_SIZE as u64);
+        checkpoint.put_u8(FLAG_LEAVES);
+        assert!(matches!(
+            CidBuilder::resume(&checkpoint),
+            Err(CheckpointError::Inconsistent)
+        ));
+    }
+}
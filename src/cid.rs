@@ -3,7 +3,7 @@ use bytes_varint::{VarIntSupport, VarIntSupportMut};
 use sha2::{Digest, Sha256};
 use std::{
     fmt::{self, Debug, Display, Write},
-    fs::File,
+    fs::{File, Metadata},
     io, mem,
     str::FromStr,
     sync::Arc,
@@ -11,10 +11,20 @@ use std::{
 };
 use thiserror::Error;
 
-use crate::{Hash, BLOCK_SIZE};
+use crate::{
+    hasher::{AnyHasher, CidHasher},
+    tree::{BlockProof, MerkleTree, RangeProof, SubtreeStack, TreeHasher},
+    CidVersion, Encoding, Hash, BLOCK_SIZE,
+};
 
 #[derive(Error, Debug)]
 pub enum CidDecodeError {
+    #[error("empty input")]
+    Empty,
+
+    #[error("invalid version character: {char:?}")]
+    InvalidVersionChar { char: char },
+
     #[error("unsupported version: {version}")]
     UnsupportedVersion { version: u8 },
 
@@ -26,44 +36,123 @@ pub enum CidDecodeError {
 
     #[error("invalid hash")]
     InvalidHash,
+
+    #[error("non-canonical encoding")]
+    NonCanonical,
+
+    #[error("invalid multihash")]
+    InvalidMultihash,
+
+    #[error("invalid cidv1")]
+    InvalidCidV1,
+
+    #[error("checksum mismatch")]
+    ChecksumMismatch,
 }
 
-#[derive(Hash, PartialEq, Eq)]
 struct Inner {
-    version: u8,
+    version: &'static CidVersion,
     size: u64,
     hash: Hash,
 }
+impl Inner {
+    /// The fields identifying a CID. Versions are compared by their byte
+    /// alone, as every byte has a single descriptor.
+    fn key(&self) -> (u8, u64, &Hash) {
+        (self.version.byte(), self.size, &self.hash)
+    }
+}
+impl PartialEq for Inner {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+impl Eq for Inner {}
+impl std::hash::Hash for Inner {
+    fn hash<S: std::hash::Hasher>(&self, state: &mut S) {
+        self.key().hash(state);
+    }
+}
 
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub struct Cid(Arc<Inner>);
 impl Cid {
     pub const VERSION_RAW: u8 = b'A';
 
+    /// Like [`Cid::VERSION_RAW`], but leaves, interior nodes and padding are
+    /// hashed with distinct prefixes and the size is bound into the hash.
+    pub const VERSION_TAGGED: u8 = b'B';
+
+    /// Same tree as [`Cid::VERSION_TAGGED`], hashed with BLAKE3.
+    pub const VERSION_BLAKE3: u8 = b'C';
+
+    /// Same tree as [`Cid::VERSION_TAGGED`], hashed with SHA-512/256.
+    pub const VERSION_SHA512_256: u8 = b'D';
+
+    /// Same as [`Cid::VERSION_BLAKE3`], with 256 KiB blocks.
+    pub const VERSION_BLAKE3_256K: u8 = b'E';
+
+    /// Same as [`Cid::VERSION_BLAKE3`], with 1 MiB blocks.
+    pub const VERSION_BLAKE3_1M: u8 = b'F';
+
     pub const MAX_SIZE_IN_BYTES: usize = 1 + 9 + mem::size_of::<Hash>();
 
+    /// Largest content size a CID can describe, so that the size always fits
+    /// in 9 varint bytes.
+    pub const MAX_SIZE: u64 = i64::MAX as u64;
+
+    /// # Panics
+    ///
+    /// Panics if the version is not supported. See [`Cid::try_builder`] for a
+    /// fallible version.
     pub fn builder(version: u8) -> CidBuilder {
-        CidBuilder {
-            version,
-            size: 0,
-            head: 0,
-            hasher: Sha256::new(),
-            leaves: Vec::new(),
-        }
+        Self::try_builder(version).expect("unsupported cid version")
+    }
+
+    pub fn try_builder(version: u8) -> Result<CidBuilder, CidError> {
+        let info = CidVersion::get(version).ok_or(CidError::UnsupportedVersion { version })?;
+        CidBuilder::try_with_hasher(version, info.hash_algorithm().hasher())
     }
 
+    /// # Panics
+    ///
+    /// Panics if [`Cid::try_new`] would fail.
     pub fn new(version: u8, size: u64, hash: Hash) -> Self {
-        Self(Arc::new(Inner {
+        Self::try_new(version, size, hash).expect("invalid cid")
+    }
+
+    /// Creates a CID from its parts, failing if the version is not supported
+    /// or `size` exceeds [`Cid::MAX_SIZE`].
+    pub fn try_new(version: u8, size: u64, hash: Hash) -> Result<Self, CidDecodeError> {
+        let Some(version) = CidVersion::get(version) else {
+            return Err(CidDecodeError::UnsupportedVersion { version });
+        };
+        if size > Self::MAX_SIZE {
+            return Err(CidDecodeError::InvalidSize);
+        }
+        Ok(Self(Arc::new(Inner {
             version,
             size,
             hash,
-        }))
+        })))
     }
 
-    pub fn from_reader(version: u8, mut reader: impl io::Read) -> io::Result<Self> {
-        let mut builder = Self::builder(version);
+    pub fn from_reader(version: u8, reader: impl io::Read) -> Result<Self, CidError> {
+        Self::from_reader_inspect(version, reader, |_| Ok(()))
+    }
+
+    /// Like [`Cid::from_reader`], but calls `inspect` with the number of
+    /// bytes hashed so far before every read. Hashing stops at the first error
+    /// it returns.
+    pub(crate) fn from_reader_inspect(
+        version: u8,
+        mut reader: impl io::Read,
+        mut inspect: impl FnMut(u64) -> Result<(), CidError>,
+    ) -> Result<Self, CidError> {
+        let mut builder = Self::try_builder(version)?;
         let mut buf = [0; BLOCK_SIZE];
         loop {
+            inspect(builder.size)?;
             let n = reader.read(&mut buf)?;
             if n == 0 {
                 break;
@@ -73,55 +162,110 @@ impl Cid {
         Ok(builder.finalize())
     }
 
-    pub fn from_file(version: u8, file: &mut File) -> io::Result<(Self, SystemTime)> {
-        let modified = file.metadata()?.modified()?;
-        let cid = Self::from_reader(version, &mut *file)?;
-        let new_modified = file.metadata()?.modified()?;
-        if modified != new_modified {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "file modified while reading",
-            ));
-        }
+    /// Hashes the whole of `file`, which must be positioned at its start, and
+    /// returns the CID along with the file's modification time.
+    ///
+    /// Fails with [`CidError::FileChanged`] if the file changed while it was
+    /// being read, or [`CidError::SizeMismatch`] if it was not read in full.
+    pub fn from_file(version: u8, file: &mut File) -> Result<(Self, SystemTime), CidError> {
+        Self::hash_unmodified(file, |file| Self::from_reader(version, file))
+    }
+
+    /// Runs `hash` on `file` and fails if the file changed meanwhile or `hash`
+    /// did not cover all of it. Returns the modification time along with the
+    /// CID.
+    pub(crate) fn hash_unmodified(
+        file: &mut File,
+        hash: impl FnOnce(&mut File) -> Result<Self, CidError>,
+    ) -> Result<(Self, SystemTime), CidError> {
+        let metadata = file.metadata()?;
+        let cid = hash(file)?;
+        let modified = ensure_unchanged(&metadata, &file.metadata()?, cid.size())?;
         Ok((cid, modified))
     }
 
+    /// # Panics
+    ///
+    /// Panics if the version is not supported.
     pub fn from_data(version: u8, data: impl AsRef<[u8]>) -> Cid {
         let mut builder = Self::builder(version);
         builder.update(data);
         builder.finalize()
     }
 
+    /// Decodes a CID from exactly `bytes`, which must be in the canonical form
+    /// written by [`Cid::encode`].
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, CidDecodeError> {
-        let (version, bytes) = bytes.split_at(1);
-        Self::from_version_and_buf(version[0], bytes)
+        Self::decode(bytes)
+    }
+
+    /// Like [`Cid::from_bytes`], but also accepts overlong size encodings and
+    /// sizes above [`Cid::MAX_SIZE`], as found in legacy data.
+    pub fn from_bytes_lenient(bytes: &[u8]) -> Result<Self, CidDecodeError> {
+        Self::decode_lenient(bytes)
     }
 
     pub fn encode(&self, buf: &mut impl BufMut) {
-        buf.put_u8(self.0.version);
+        buf.put_u8(self.0.version.byte());
         buf.put_u64_varint(self.0.size);
         buf.put_slice(&self.0.hash);
     }
 
-    pub fn decode(mut buf: impl Buf) -> Result<Self, CidDecodeError> {
-        let version = buf.get_u8();
-        Self::from_version_and_buf(version, buf)
+    /// Decodes a CID from all of `buf`, which must be in the canonical form
+    /// written by [`Cid::encode`].
+    pub fn decode(buf: impl Buf) -> Result<Self, CidDecodeError> {
+        Self::decode_exact(buf, true)
+    }
+
+    /// Like [`Cid::decode`], but also accepts overlong size encodings and
+    /// sizes above [`Cid::MAX_SIZE`], as found in legacy data.
+    pub fn decode_lenient(buf: impl Buf) -> Result<Self, CidDecodeError> {
+        Self::decode_exact(buf, false)
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(Self::MAX_SIZE_IN_BYTES);
+        let mut buf = Vec::with_capacity(self.encoded_len());
         self.encode(&mut buf);
         buf
     }
 
-    fn from_version_and_buf(version: u8, mut buf: impl Buf) -> Result<Self, CidDecodeError> {
-        if version != Self::VERSION_RAW {
-            return Err(CidDecodeError::UnsupportedVersion { version });
+    /// Decodes one CID from the front of `buf` and leaves whatever follows it,
+    /// so that encoded CIDs can be embedded in larger messages. On error, the
+    /// position of `buf` is unspecified.
+    pub fn decode_from(buf: &mut impl Buf) -> Result<Self, CidDecodeError> {
+        Self::decode_body(buf, true)
+    }
+
+    /// Length of the encoding written by [`Cid::encode`].
+    pub fn encoded_len(&self) -> usize {
+        1 + varint_len(self.0.size) + self.0.hash.len()
+    }
+
+    pub(crate) fn decode_exact(mut buf: impl Buf, strict: bool) -> Result<Self, CidDecodeError> {
+        let cid = Self::decode_body(&mut buf, strict)?;
+        if buf.has_remaining() {
+            return Err(if strict {
+                CidDecodeError::NonCanonical
+            } else {
+                CidDecodeError::InvalidHash
+            });
         }
+        Ok(cid)
+    }
+
+    fn decode_body(buf: &mut impl Buf, strict: bool) -> Result<Self, CidDecodeError> {
+        let version = buf.try_get_u8().map_err(|_| CidDecodeError::Empty)?;
+        let Some(version) = CidVersion::get(version) else {
+            return Err(CidDecodeError::UnsupportedVersion { version });
+        };
+        let remaining = buf.remaining();
         let size = buf
-            .get_u64_varint()
+            .try_get_u64_varint()
             .map_err(|_| CidDecodeError::InvalidSize)?;
-        if buf.remaining() != mem::size_of::<Hash>() {
+        if strict && (remaining - buf.remaining() != varint_len(size) || size > Self::MAX_SIZE) {
+            return Err(CidDecodeError::NonCanonical);
+        }
+        if buf.remaining() < version.digest_len() {
             return Err(CidDecodeError::InvalidHash);
         }
         let mut hash = Hash::default();
@@ -134,6 +278,10 @@ impl Cid {
     }
 
     pub fn version(&self) -> u8 {
+        self.0.version.byte()
+    }
+
+    pub fn version_info(&self) -> &'static CidVersion {
         self.0.version
     }
 
@@ -146,70 +294,357 @@ impl Cid {
     }
 
     pub fn num_blocks(&self) -> u64 {
-        self.0.size.div_ceil(BLOCK_SIZE as u64)
+        self.0.version.num_blocks(self.0.size)
     }
 
     pub fn is_raw(&self) -> bool {
-        self.0.version == Self::VERSION_RAW
+        self.version() == Self::VERSION_RAW
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.0.version.block_size()
+    }
+
+    /// Returns the byte offset at which the block at `index` starts, or `None`
+    /// if the index is out of range.
+    pub fn block_offset(&self, index: u64) -> Option<u64> {
+        if index >= self.num_blocks() {
+            return None;
+        }
+        Some(index * self.block_size() as u64)
+    }
+
+    /// Returns the length of the block at `index`, or `None` if the index is
+    /// out of range.
+    pub fn block_len(&self, index: u64) -> Option<usize> {
+        let offset = self.block_offset(index)?;
+        Some(std::cmp::min(self.block_size() as u64, self.0.size - offset) as usize)
+    }
+
+    /// Returns the index of the block containing the byte at `offset`, or
+    /// `None` if the offset is past the end of the content.
+    pub fn block_index(&self, offset: u64) -> Option<u64> {
+        if offset >= self.0.size {
+            return None;
+        }
+        Some(offset / self.block_size() as u64)
+    }
+
+    /// Creates a CID from the root of its Merkle tree.
+    pub(crate) fn from_root<H: CidHasher>(
+        version: &'static CidVersion,
+        tree: &TreeHasher<H>,
+        size: u64,
+        root: &Hash,
+    ) -> Self {
+        Self(Arc::new(Inner {
+            version,
+            size,
+            hash: tree.finish(root, size),
+        }))
+    }
+
+    pub(crate) fn tree_hasher(&self) -> TreeHasher {
+        self.0.version.tree_hasher()
+    }
+
+    /// Checks that `root` is the root of the Merkle tree of this CID.
+    pub(crate) fn matches_root(&self, root: &Hash) -> bool {
+        self.tree_hasher().finish(root, self.0.size) == self.0.hash
+    }
+
+    /// Checks that `data` is the block at `index` of the content identified
+    /// by this CID.
+    pub fn verify_block(&self, index: u64, data: &[u8], proof: &BlockProof) -> bool {
+        if self.block_len(index) != Some(data.len()) {
+            return false;
+        }
+        let depth = self.num_blocks().next_power_of_two().trailing_zeros() as usize;
+        if proof.siblings().len() != depth {
+            return false;
+        }
+        let tree = self.tree_hasher();
+        self.matches_root(&proof.root_of(&tree, index, tree.hash_leaf(data)))
+    }
+
+    /// Checks that `data` is the content of this CID starting at block
+    /// `start`. The data must consist of whole blocks, except that it may end
+    /// with the last, shorter block of the content.
+    pub fn verify_range(&self, start: u64, data: &[u8], proof: &RangeProof) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        let tree = self.tree_hasher();
+        let mut leaves = Vec::new();
+        for (i, block) in data.chunks(self.block_size()).enumerate() {
+            if self.block_len(start + i as u64) != Some(block.len()) {
+                return false;
+            }
+            leaves.push(tree.hash_leaf(block));
+        }
+        let width = self.num_blocks().next_power_of_two();
+        proof
+            .root_of(&tree, start, leaves, width)
+            .is_some_and(|root| self.matches_root(&root))
     }
 }
 
-pub struct CidBuilder {
-    version: u8,
-    size: u64,
-    head: usize,
-    hasher: Sha256,
-    leaves: Vec<Hash>,
+/// Errors that can occur while hashing content into a [`Cid`].
+#[derive(Error, Debug)]
+pub enum CidError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    FileChanged(#[from] FileChanged),
+
+    #[error("hashing cancelled")]
+    Cancelled,
+
+    #[error("hashed {read} bytes of a file of {len} bytes")]
+    SizeMismatch { read: u64, len: u64 },
+
+    #[error("unsupported version: {version}")]
+    UnsupportedVersion { version: u8 },
+
+    #[error("hasher does not match version: {version}")]
+    HasherMismatch { version: u8 },
+}
+impl From<CidError> for io::Error {
+    fn from(err: CidError) -> Self {
+        match err {
+            CidError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
+    }
+}
+
+/// How a file changed while it was being hashed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FileChanged {
+    #[error("file modified during hashing")]
+    Modified,
+
+    #[error("file size changed during hashing")]
+    Resized,
+
+    #[error("file replaced during hashing")]
+    Replaced,
+
+    #[error("file status changed during hashing")]
+    StatusChanged,
 }
-impl CidBuilder {
+
+/// Checks that a file has not changed between taking the two metadata
+/// snapshots and that `read` bytes of it were hashed, and returns its
+/// modification time.
+///
+/// Besides the modification time, the inode, device and status change time
+/// are compared on Unix, which catch writes that fall within the timestamp
+/// granularity of the filesystem. The size checks only apply to regular
+/// files, and the number of bytes read is not checked against a length of
+/// zero, as pipes, devices and `/proc` entries do not report their size.
+pub(crate) fn ensure_unchanged(
+    before: &Metadata,
+    after: &Metadata,
+    read: u64,
+) -> Result<SystemTime, CidError> {
+    let modified = before.modified()?;
+    if modified != after.modified()? {
+        return Err(FileChanged::Modified.into());
+    }
+    if before.is_file() && before.len() != after.len() {
+        return Err(FileChanged::Resized.into());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if before.dev() != after.dev() || before.ino() != after.ino() {
+            return Err(FileChanged::Replaced.into());
+        }
+        if (before.ctime(), before.ctime_nsec()) != (after.ctime(), after.ctime_nsec()) {
+            return Err(FileChanged::StatusChanged.into());
+        }
+    }
+    if before.is_file() && before.len() != 0 && read != before.len() {
+        return Err(CidError::SizeMismatch {
+            read,
+            len: before.len(),
+        });
+    }
+    Ok(modified)
+}
+
+/// Incrementally computes a [`Cid`].
+///
+/// Leaves are folded into subtree roots as soon as they are complete, so the
+/// builder only holds a logarithmic number of hashes unless
+/// [`CidBuilder::with_tree`] asks it to keep all of them.
+///
+/// The hash function is picked at runtime from the version by default; use
+/// [`CidBuilder::with_hasher`] to hash with a concrete [`CidHasher`] instead.
+pub struct CidBuilder<H = AnyHasher> {
+    pub(crate) version: &'static CidVersion,
+    pub(crate) tree: TreeHasher<H>,
+    pub(crate) size: u64,
+    pub(crate) head: usize,
+    hasher: H,
+    pub(crate) stack: SubtreeStack,
+    pub(crate) leaves: Option<Vec<Hash>>,
+}
+impl<H: CidHasher> CidBuilder<H> {
+    /// Creates a builder hashing with `hasher`, which must not have been fed
+    /// any data yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if [`CidBuilder::try_with_hasher`] would fail.
+    pub fn with_hasher(version: u8, hasher: H) -> Self {
+        Self::try_with_hasher(version, hasher).expect("invalid cid builder")
+    }
+
+    /// Like [`CidBuilder::with_hasher`], but fails if the version is not
+    /// supported or does not use the hash function of `hasher`.
+    pub fn try_with_hasher(version: u8, hasher: H) -> Result<Self, CidError> {
+        let Some(info) = CidVersion::get(version) else {
+            return Err(CidError::UnsupportedVersion { version });
+        };
+        if hasher.algorithm() != info.hash_algorithm() {
+            return Err(CidError::HasherMismatch { version });
+        }
+        let tree = TreeHasher::new(info.tree_mode(), hasher);
+        Ok(Self {
+            version: info,
+            hasher: tree.leaf_hasher(),
+            tree,
+            size: 0,
+            head: 0,
+            stack: SubtreeStack::default(),
+            leaves: None,
+        })
+    }
+
+    /// Keeps every leaf hash so that [`CidBuilder::finalize_with_tree`] can
+    /// return the whole tree. This makes memory grow with the content size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if data has already been added.
+    pub fn with_tree(mut self) -> Self {
+        assert_eq!(self.size, 0, "with_tree must be called before adding data");
+        self.leaves = Some(Vec::new());
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the version is not supported, or if it does not hash content
+    /// the same way as the current one.
     pub fn set_version(&mut self, version: u8) {
+        let version = CidVersion::get(version).expect("unsupported cid version");
+        assert!(
+            version.same_scheme(self.version),
+            "cannot change the hashing scheme of a builder"
+        );
         self.version = version;
     }
 
     pub fn update(&mut self, data: impl AsRef<[u8]>) {
         let mut data = data.as_ref();
+        let block_size = self.block_size();
         self.size += data.len() as u64;
         while !data.is_empty() {
-            let n = std::cmp::min(data.len(), BLOCK_SIZE - self.head);
+            let n = std::cmp::min(data.len(), block_size - self.head);
             let (left, right) = data.split_at(n);
             self.hasher.update(left);
             data = right;
             self.head += n;
-            if self.head == BLOCK_SIZE {
+            if self.head == block_size {
                 self.head = 0;
-                let hasher = mem::replace(&mut self.hasher, Sha256::new());
-                self.leaves.push(hasher.finalize().into());
+                self.finish_leaf();
             }
         }
     }
 
+    pub(crate) fn block_size(&self) -> usize {
+        self.version.block_size()
+    }
+
+    /// Number of bytes missing from the current block, zero if the builder is
+    /// at a block boundary.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn block_remaining(&self) -> usize {
+        (self.block_size() - self.head) % self.block_size()
+    }
+
+    #[cfg(feature = "rayon")]
+    pub(crate) fn tree(&self) -> &TreeHasher<H> {
+        &self.tree
+    }
+
+    /// Adds the leaf hashes of `len` bytes of whole blocks, hashed elsewhere.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn push_leaves(&mut self, leaves: impl IntoIterator<Item = Hash>, len: u64) {
+        debug_assert_eq!(self.head, 0);
+        self.size += len;
+        for leaf in leaves {
+            self.push_leaf(leaf);
+        }
+    }
+
+    fn push_leaf(&mut self, leaf: Hash) {
+        if let Some(leaves) = &mut self.leaves {
+            leaves.push(leaf);
+        }
+        self.stack.push(&self.tree, leaf);
+    }
+
+    fn finish_leaf(&mut self) {
+        let hasher = mem::replace(&mut self.hasher, self.tree.leaf_hasher());
+        self.push_leaf(hasher.finalize());
+    }
+
     pub fn finalize(mut self) -> Cid {
         if self.head != 0 {
-            self.leaves.push(self.hasher.finalize().into());
+            self.finish_leaf();
         }
-        let hash = get_root(&self.leaves);
-        Cid::new(self.version, self.size, hash)
+        let root = self.stack.root(&self.tree);
+        Cid::from_root(self.version, &self.tree, self.size, &root)
+    }
+
+    /// Like [`CidBuilder::finalize`], but also returns the Merkle tree the
+    /// root was computed from, which can be used to produce block proofs. The
+    /// tree is only available if the builder was created with
+    /// [`CidBuilder::with_tree`].
+    pub fn finalize_with_tree(mut self) -> (Cid, Option<MerkleTree>) {
+        if self.leaves.is_none() {
+            return (self.finalize(), None);
+        }
+        if self.head != 0 {
+            self.finish_leaf();
+        }
+        let leaves = self.leaves.take().unwrap_or_default();
+        let tree = MerkleTree::build(self.version.byte(), &self.tree, &leaves);
+        let cid = Cid::from_root(self.version, &self.tree, self.size, tree.root());
+        (cid, Some(tree))
     }
 }
 
-fn get_root(leaves: &[Hash]) -> Hash {
-    let size = leaves.len().next_power_of_two();
-    let mut hashes = Vec::with_capacity(size * 2 - 1);
-    hashes.resize_with(size - 1, Hash::default);
-    hashes.extend_from_slice(leaves);
-    hashes.resize_with(size * 2 - 1, Hash::default);
-    for i in (0..size - 1).rev() {
-        let mut hasher = Sha256::new();
-        hasher.update(&hashes[i * 2 + 1]);
-        hasher.update(&hashes[i * 2 + 2]);
-        hashes[i] = hasher.finalize().into();
-    }
-    hashes[0]
+impl<H: CidHasher> io::Write for CidBuilder<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 impl Display for Cid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_char(self.0.version as char)?;
+        f.write_char(self.0.version.byte() as char)?;
         let mut buf = Vec::with_capacity(Self::MAX_SIZE_IN_BYTES - 1);
         buf.put_u64_varint(self.0.size);
         buf.extend(&self.0.hash);
@@ -219,23 +654,94 @@ impl Display for Cid {
 impl Debug for Cid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Cid")
-            .field("version", &self.0.version)
+            .field("version", &(self.0.version.byte() as char))
             .field("size", &self.0.size)
-            .field("hash", &hex::encode(&self.0.hash))
+            .field("hash", &hex::encode(self.0.hash))
             .finish()
     }
 }
 
+/// Number of bytes `value` takes as a varint.
+pub(crate) fn varint_len(value: u64) -> usize {
+    let bits = u64::BITS - value.leading_zeros();
+    bits.div_ceil(7).max(1) as usize
+}
+
+impl Cid {
+    /// Like [`Cid::from_str`], but decodes the bytes of the CID as
+    /// [`Cid::from_bytes_lenient`] does.
+    pub fn from_str_lenient(s: &str) -> Result<Self, CidDecodeError> {
+        Self::parse(s, false)
+    }
+
+    /// Formats the CID with the given encoding. [`Encoding::Base58`] gives
+    /// the same string as [`Display`].
+    pub fn to_string_with(&self, encoding: Encoding) -> String {
+        match encoding {
+            Encoding::Base58 => self.to_string(),
+            encoding => encoding.encode(&self.to_bytes()),
+        }
+    }
+
+    /// Formats the CID with the given encoding, followed by `-` and a
+    /// checksum over its bytes, so that typos are caught when the string is
+    /// parsed back instead of yielding another valid CID.
+    ///
+    /// `-` never occurs in base58, base32 or hex output, and keeps the
+    /// checksummed string usable as a DNS label or a file name. Returns `None`
+    /// for [`Encoding::Base64Url`], whose alphabet includes `-` and `_`.
+    pub fn to_string_checksummed(&self, encoding: Encoding) -> Option<String> {
+        if encoding == Encoding::Base64Url {
+            return None;
+        }
+        Some(format!(
+            "{}-{}",
+            self.to_string_with(encoding),
+            self.checksum()
+        ))
+    }
+
+    /// The first four bytes of the SHA-256 of the encoded CID, in hex.
+    fn checksum(&self) -> String {
+        hex::encode(&Sha256::digest(self.to_bytes())[..4])
+    }
+
+    /// Parses any of the [`Encoding`]s, telling them apart by their first
+    /// character, and checks the checksum if there is one.
+    fn parse(s: &str, strict: bool) -> Result<Self, CidDecodeError> {
+        let base64 = s.starts_with(Encoding::Base64Url.prefix().unwrap());
+        if let Some((s, checksum)) = s.split_once('-').filter(|_| !base64) {
+            let cid = Self::parse(s, strict)?;
+            if checksum != cid.checksum() {
+                return Err(CidDecodeError::ChecksumMismatch);
+            }
+            return Ok(cid);
+        }
+        let mut chars = s.chars();
+        let char = chars.next().ok_or(CidDecodeError::Empty)?;
+        let s = chars.as_str();
+        let bytes = if char.is_ascii_uppercase() {
+            let buf = bs58::decode(s)
+                .into_vec()
+                .map_err(|_| CidDecodeError::InvalidEncoding)?;
+            let mut bytes = vec![char as u8];
+            bytes.extend(buf);
+            bytes
+        } else {
+            Encoding::from_prefix(char)
+                .ok_or(CidDecodeError::InvalidVersionChar { char })?
+                .decode(s)
+                .ok_or(CidDecodeError::InvalidEncoding)?
+        };
+        Self::decode_exact(bytes.as_slice(), strict)
+    }
+}
+
 impl FromStr for Cid {
     type Err = CidDecodeError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (version, s) = s.split_at(1);
-        let version = version.as_bytes()[0];
-        let buf = bs58::decode(s)
-            .into_vec()
-            .map_err(|_| CidDecodeError::InvalidEncoding)?;
-        Self::from_version_and_buf(version, buf.as_slice())
+        Self::parse(s, true)
     }
 }
 
@@ -253,6 +759,49 @@ mod test {
         assert_eq!(cid1, cid2);
     }
 
+    #[test]
+    fn tagged_binds_size() {
+        let raw = Cid::from_data(Cid::VERSION_RAW, [0; 10]);
+        let tagged = Cid::from_data(Cid::VERSION_TAGGED, [0; 10]);
+        assert_ne!(raw.hash(), tagged.hash());
+
+        let empty = Cid::from_data(Cid::VERSION_TAGGED, []);
+        assert_ne!(empty.hash(), &Hash::default());
+        let s = tagged.to_string();
+        assert!(s.starts_with('B'));
+        assert_eq!(Cid::from_str(&s).unwrap(), tagged);
+    }
+
+    #[test]
+    fn large_blocks() {
+        let data = crate::test_data((1 << 20) * 2 + 5);
+        let (cid, tree) = crate::test_tree(Cid::VERSION_BLAKE3_1M, &data);
+        assert_eq!(cid.block_size(), 1 << 20);
+        assert_eq!(cid.num_blocks(), 3);
+        assert_eq!(tree.num_leaves(), 3);
+        assert_eq!(cid.block_offset(2), Some(2 << 20));
+        assert_eq!(cid.block_len(2), Some(5));
+        assert_eq!(cid.block_index((2 << 20) + 4), Some(2));
+        assert_eq!(cid.block_index((2 << 20) + 5), None);
+
+        let proof = tree.proof(1).unwrap();
+        assert!(cid.verify_block(1, &data[1 << 20..2 << 20], &proof));
+        assert_ne!(
+            cid.hash(),
+            Cid::from_data(Cid::VERSION_BLAKE3, &data).hash()
+        );
+    }
+
+    #[test]
+    fn tree_is_opt_in() {
+        let data = [5; BLOCK_SIZE + 1];
+        let mut builder = Cid::builder(Cid::VERSION_RAW);
+        builder.update(data);
+        let (cid, tree) = builder.finalize_with_tree();
+        assert_eq!(cid, Cid::from_data(Cid::VERSION_RAW, data));
+        assert!(tree.is_none());
+    }
+
     #[test]
     fn cid_display() {
         let cid = Cid::new(Cid::VERSION_RAW, 10, [1; 32]);
@@ -260,4 +809,207 @@ mod test {
         let cid2 = Cid::from_str(&s).unwrap();
         assert_eq!(cid, cid2);
     }
+
+    #[test]
+    fn file_changes() {
+        use io::{Seek, Write};
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[7; BLOCK_SIZE * 2]).unwrap();
+        file.rewind().unwrap();
+        let (cid, _) = Cid::from_file(Cid::VERSION_RAW, &mut file).unwrap();
+        assert_eq!(cid, Cid::from_data(Cid::VERSION_RAW, [7; BLOCK_SIZE * 2]));
+
+        file.seek(io::SeekFrom::Start(1)).unwrap();
+        assert!(matches!(
+            Cid::from_file(Cid::VERSION_RAW, &mut file),
+            Err(CidError::SizeMismatch { read, len })
+                if read == BLOCK_SIZE as u64 * 2 - 1 && len == BLOCK_SIZE as u64 * 2
+        ));
+
+        file.rewind().unwrap();
+        let writer = file.try_clone().unwrap();
+        let result = Cid::hash_unmodified(&mut file, |file| {
+            writer.set_len(1).unwrap();
+            Cid::from_reader(Cid::VERSION_RAW, file)
+        });
+        assert!(matches!(
+            result,
+            Err(CidError::FileChanged(
+                FileChanged::Modified | FileChanged::Resized
+            ))
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn non_regular_file() {
+        let mut file = File::open("/proc/self/status").unwrap();
+        let (cid, _) = Cid::from_file(Cid::VERSION_RAW, &mut file).unwrap();
+        assert!(cid.size() > 0);
+    }
+
+    #[test]
+    fn malformed_input() {
+        assert!(matches!(Cid::from_bytes(&[]), Err(CidDecodeError::Empty)));
+        assert!(matches!(Cid::decode(&[][..]), Err(CidDecodeError::Empty)));
+        assert!(matches!(Cid::from_str(""), Err(CidDecodeError::Empty)));
+        assert!(matches!(
+            Cid::from_str("éabc"),
+            Err(CidDecodeError::InvalidVersionChar { char: 'é' })
+        ));
+        assert!(matches!(
+            Cid::from_str("Z"),
+            Err(CidDecodeError::UnsupportedVersion { version: b'Z' })
+        ));
+        assert!(Cid::from_str("A").is_err());
+        assert!(Cid::from_bytes(b"A").is_err());
+    }
+
+    #[test]
+    fn decode_from_frame() {
+        let cids = [
+            Cid::from_data(Cid::VERSION_RAW, b""),
+            Cid::new(Cid::VERSION_TAGGED, 300, [2; 32]),
+            Cid::new(Cid::VERSION_BLAKE3, Cid::MAX_SIZE, [3; 32]),
+        ];
+        let mut frame = Vec::new();
+        for cid in &cids {
+            assert_eq!(cid.to_bytes().len(), cid.encoded_len());
+            cid.encode(&mut frame);
+        }
+        frame.extend_from_slice(b"rest");
+
+        let mut buf = frame.as_slice();
+        for cid in &cids {
+            assert_eq!(&Cid::decode_from(&mut buf).unwrap(), cid);
+        }
+        assert_eq!(buf, b"rest");
+        assert!(Cid::decode(frame.as_slice()).is_err());
+        assert!(Cid::decode_from(&mut &frame[..frame.len() - 5]).is_ok());
+        assert!(Cid::decode_from(&mut &cids[0].to_bytes()[..20]).is_err());
+    }
+
+    #[test]
+    fn canonical_encoding() {
+        let cid = Cid::new(Cid::VERSION_RAW, 5, [1; 32]);
+        let mut overlong = vec![Cid::VERSION_RAW, 0x85, 0x00];
+        overlong.extend([1; 32]);
+        assert!(matches!(
+            Cid::from_bytes(&overlong),
+            Err(CidDecodeError::NonCanonical)
+        ));
+        assert_eq!(Cid::from_bytes_lenient(&overlong).unwrap(), cid);
+
+        let s = format!("A{}", bs58::encode(&overlong[1..]).into_string());
+        assert!(matches!(
+            Cid::from_str(&s),
+            Err(CidDecodeError::NonCanonical)
+        ));
+        assert_eq!(Cid::from_str_lenient(&s).unwrap(), cid);
+
+        let mut trailing = cid.to_bytes();
+        trailing.push(0);
+        assert!(matches!(
+            Cid::from_bytes(&trailing),
+            Err(CidDecodeError::NonCanonical)
+        ));
+        assert!(Cid::from_bytes_lenient(&trailing).is_err());
+
+        let mut too_large = vec![Cid::VERSION_RAW];
+        too_large.put_u64_varint(Cid::MAX_SIZE + 1);
+        too_large.extend([1; 32]);
+        assert!(matches!(
+            Cid::from_bytes(&too_large),
+            Err(CidDecodeError::NonCanonical)
+        ));
+        assert_eq!(
+            Cid::from_bytes_lenient(&too_large).unwrap().size(),
+            Cid::MAX_SIZE + 1
+        );
+    }
+
+    #[test]
+    fn encodings() {
+        let cid = Cid::from_data(Cid::VERSION_TAGGED, b"hello");
+        for encoding in [
+            Encoding::Base58,
+            Encoding::Base32Lower,
+            Encoding::Base64Url,
+            Encoding::Hex,
+        ] {
+            let s = cid.to_string_with(encoding);
+            assert_eq!(s.chars().next(), encoding.prefix().or(Some('B')));
+            assert_eq!(Cid::from_str(&s).unwrap(), cid);
+        }
+        assert_eq!(cid.to_string_with(Encoding::Base58), cid.to_string());
+
+        let base32 = cid.to_string_with(Encoding::Base32Lower);
+        assert!(!base32.bytes().any(|b| b.is_ascii_uppercase()));
+        assert!(Cid::from_str(&base32.to_uppercase()).is_err());
+        assert!(Cid::from_str(&format!("f{}", hex::encode([1, 2]))).is_err());
+        assert!(matches!(
+            Cid::from_str("xabc"),
+            Err(CidDecodeError::InvalidVersionChar { char: 'x' })
+        ));
+    }
+
+    #[test]
+    fn checksummed() {
+        let cid = Cid::from_data(Cid::VERSION_RAW, b"hello");
+        for encoding in [Encoding::Base58, Encoding::Base32Lower, Encoding::Hex] {
+            let s = cid.to_string_checksummed(encoding).unwrap();
+            let (plain, checksum) = s.split_once('-').unwrap();
+            assert_eq!(plain, cid.to_string_with(encoding));
+            assert_eq!(checksum.len(), 8);
+            assert_eq!(Cid::from_str(&s).unwrap(), cid);
+        }
+        let base32 = cid.to_string_checksummed(Encoding::Base32Lower).unwrap();
+        assert!(base32
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-'));
+
+        assert!(cid.to_string_checksummed(Encoding::Base64Url).is_none());
+        let base64 = (0..)
+            .map(|i: u32| Cid::from_data(Cid::VERSION_RAW, i.to_le_bytes()))
+            .map(|cid| cid.to_string_with(Encoding::Base64Url))
+            .find(|s| s.contains('-'))
+            .unwrap();
+        assert!(Cid::from_str(&base64).is_ok());
+
+        let s = cid.to_string_checksummed(Encoding::Hex).unwrap();
+        let (plain, checksum) = s.split_once('-').unwrap();
+        let last = if plain.ends_with('0') { '1' } else { '0' };
+        let typo = format!("{}{last}-{checksum}", &plain[..plain.len() - 1]);
+        assert!(Cid::from_str(typo.split_once('-').unwrap().0).is_ok());
+        assert!(matches!(
+            Cid::from_str(&typo),
+            Err(CidDecodeError::ChecksumMismatch)
+        ));
+
+        let other = Cid::from_data(Cid::VERSION_RAW, b"world");
+        let swapped = format!("{}-{}", cid, other.checksum());
+        assert!(matches!(
+            Cid::from_str(&swapped),
+            Err(CidDecodeError::ChecksumMismatch)
+        ));
+        assert!(Cid::from_str(&format!("{cid}-")).is_err());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert!(matches!(
+            Cid::from_reader(b'Z', &b"hello"[..]),
+            Err(CidError::UnsupportedVersion { version: b'Z' })
+        ));
+        assert!(Cid::try_builder(b'Z').is_err());
+        assert!(matches!(
+            Cid::try_new(b'Z', 0, [0; 32]),
+            Err(CidDecodeError::UnsupportedVersion { version: b'Z' })
+        ));
+        assert!(matches!(
+            Cid::try_new(Cid::VERSION_RAW, Cid::MAX_SIZE + 1, [0; 32]),
+            Err(CidDecodeError::InvalidSize)
+        ));
+    }
 }
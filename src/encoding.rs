@@ -0,0 +1,76 @@
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD, HEXLOWER};
+
+/// Textual encodings of a [`Cid`](crate::Cid).
+///
+/// [`Encoding::Base58`] is the form written by `Display`: the version
+/// character followed by the base58 size and hash. The other encodings cover
+/// all the bytes written by [`Cid::encode`](crate::Cid::encode) and are led by
+/// a lowercase multibase prefix, which can never be mistaken for the
+/// uppercase version character of the base58 form.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    #[default]
+    Base58,
+    /// Lowercase RFC 4648 base32 without padding, prefixed with `b`. Suitable
+    /// for case-insensitive contexts such as DNS labels and file names.
+    Base32Lower,
+    /// URL-safe RFC 4648 base64 without padding, prefixed with `u`.
+    Base64Url,
+    /// Lowercase hexadecimal, prefixed with `f`.
+    Hex,
+}
+impl Encoding {
+    /// The multibase prefix of the encoding, or `None` for
+    /// [`Encoding::Base58`].
+    pub fn prefix(self) -> Option<char> {
+        match self {
+            Self::Base58 => None,
+            Self::Base32Lower => Some('b'),
+            Self::Base64Url => Some('u'),
+            Self::Hex => Some('f'),
+        }
+    }
+
+    pub fn from_prefix(prefix: char) -> Option<Self> {
+        match prefix {
+            'b' => Some(Self::Base32Lower),
+            'u' => Some(Self::Base64Url),
+            'f' => Some(Self::Hex),
+            _ => None,
+        }
+    }
+
+    /// Encodes `bytes` with the prefix of a multibase encoding.
+    ///
+    /// # Panics
+    ///
+    /// Panics for [`Encoding::Base58`], whose form is not a plain encoding of
+    /// the bytes.
+    pub(crate) fn encode(self, bytes: &[u8]) -> String {
+        let prefix = self.prefix().expect("base58 has no multibase prefix");
+        let encoded = match self {
+            Self::Base58 => unreachable!(),
+            Self::Base32Lower => BASE32_NOPAD.encode(bytes).to_ascii_lowercase(),
+            Self::Base64Url => BASE64URL_NOPAD.encode(bytes),
+            Self::Hex => HEXLOWER.encode(bytes),
+        };
+        format!("{prefix}{encoded}")
+    }
+
+    /// Decodes the part of a multibase string after its prefix. Only the
+    /// canonical form is accepted, so that every CID has one string per
+    /// encoding.
+    pub(crate) fn decode(self, s: &str) -> Option<Vec<u8>> {
+        match self {
+            Self::Base58 => None,
+            Self::Base32Lower => {
+                if s.bytes().any(|b| b.is_ascii_uppercase()) {
+                    return None;
+                }
+                BASE32_NOPAD.decode(s.to_ascii_uppercase().as_bytes()).ok()
+            }
+            Self::Base64Url => BASE64URL_NOPAD.decode(s.as_bytes()).ok(),
+            Self::Hex => HEXLOWER.decode(s.as_bytes()).ok(),
+        }
+    }
+}
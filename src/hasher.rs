@@ -0,0 +1,171 @@
+use sha2::{Digest, Sha256, Sha512_256};
+
+use crate::Hash;
+
+/// Hash functions a [`Cid`](crate::Cid) can be built with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    Sha256,
+    Blake3,
+    Sha512_256,
+}
+impl HashAlgorithm {
+    pub fn digest_len(self) -> usize {
+        match self {
+            Self::Sha256 | Self::Blake3 | Self::Sha512_256 => 32,
+        }
+    }
+
+    pub fn hasher(self) -> AnyHasher {
+        match self {
+            Self::Sha256 => AnyHasher::Sha256(Sha256::new()),
+            Self::Blake3 => AnyHasher::Blake3(Box::default()),
+            Self::Sha512_256 => AnyHasher::Sha512_256(Sha512_256::new()),
+        }
+    }
+}
+
+/// A hash function producing [`Hash`](crate::Hash)es, used for both leaves and interior
+/// nodes of the tree.
+///
+/// Fresh hashers are obtained by cloning one that has not been fed any data
+/// yet, so implementations should be cheap to clone in that state.
+pub trait CidHasher: Clone {
+    fn algorithm(&self) -> HashAlgorithm;
+
+    fn update(&mut self, data: &[u8]);
+
+    fn finalize(self) -> Hash;
+}
+
+impl CidHasher for Sha256 {
+    fn algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::Sha256
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finalize(self) -> Hash {
+        Digest::finalize(self).into()
+    }
+}
+
+impl CidHasher for Sha512_256 {
+    fn algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::Sha512_256
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finalize(self) -> Hash {
+        Digest::finalize(self).into()
+    }
+}
+
+impl CidHasher for blake3::Hasher {
+    fn algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::Blake3
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finalize(self) -> Hash {
+        blake3::Hasher::finalize(&self).into()
+    }
+}
+
+/// A hasher for any [`HashAlgorithm`], chosen at runtime from the version of
+/// a CID.
+#[derive(Clone)]
+pub enum AnyHasher {
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Sha512_256(Sha512_256),
+}
+impl CidHasher for AnyHasher {
+    fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            Self::Sha256(_) => HashAlgorithm::Sha256,
+            Self::Blake3(_) => HashAlgorithm::Blake3,
+            Self::Sha512_256(_) => HashAlgorithm::Sha512_256,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => CidHasher::update(hasher, data),
+            Self::Blake3(hasher) => CidHasher::update(hasher.as_mut(), data),
+            Self::Sha512_256(hasher) => CidHasher::update(hasher, data),
+        }
+    }
+
+    fn finalize(self) -> Hash {
+        match self {
+            Self::Sha256(hasher) => CidHasher::finalize(hasher),
+            Self::Blake3(hasher) => CidHasher::finalize(*hasher),
+            Self::Sha512_256(hasher) => CidHasher::finalize(hasher),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Cid, CidBuilder, CidError, CidVersion, BLOCK_SIZE};
+    use std::str::FromStr;
+
+    #[test]
+    fn static_and_dynamic_agree() {
+        let data = vec![3; BLOCK_SIZE * 2 + 1];
+        let mut builder = CidBuilder::with_hasher(Cid::VERSION_BLAKE3, blake3::Hasher::new());
+        builder.update(&data);
+        assert_eq!(
+            builder.finalize(),
+            Cid::from_data(Cid::VERSION_BLAKE3, &data)
+        );
+
+        let mut builder = CidBuilder::with_hasher(Cid::VERSION_SHA512_256, Sha512_256::new());
+        builder.update(&data);
+        assert_eq!(
+            builder.finalize(),
+            Cid::from_data(Cid::VERSION_SHA512_256, &data)
+        );
+    }
+
+    #[test]
+    fn versions_round_trip() {
+        let cids: Vec<Cid> = CidVersion::all()
+            .iter()
+            .map(|version| Cid::from_data(version.byte(), b"hello"))
+            .collect();
+        for (i, cid) in cids.iter().enumerate() {
+            assert_eq!(&Cid::from_str(&cid.to_string()).unwrap(), cid);
+            for other in &cids[i + 1..] {
+                assert_ne!(cid, other);
+                if cid.version_info().hash_algorithm() != other.version_info().hash_algorithm() {
+                    assert_ne!(cid.hash(), other.hash());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn try_mismatched_hasher() {
+        assert!(matches!(
+            CidBuilder::try_with_hasher(Cid::VERSION_BLAKE3, Sha256::new()),
+            Err(CidError::HasherMismatch { .. })
+        ));
+    }
+
+    #[test]
+    #[should_panic]
+    fn mismatched_hasher() {
+        CidBuilder::with_hasher(Cid::VERSION_BLAKE3, Sha256::new());
+    }
+}
@@ -0,0 +1,108 @@
+use std::io;
+
+use crate::{Cid, CidBuilder, CidError};
+
+/// A reader that hashes everything read through it.
+pub struct HashingReader<R> {
+    inner: R,
+    builder: CidBuilder,
+}
+impl<R: io::Read> HashingReader<R> {
+    pub fn new(version: u8, inner: R) -> Result<Self, CidError> {
+        Ok(Self {
+            inner,
+            builder: Cid::try_builder(version)?,
+        })
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the CID of the data read so far, along with the inner reader.
+    pub fn finalize(self) -> (Cid, R) {
+        (self.builder.finalize(), self.inner)
+    }
+}
+impl<R: io::Read> io::Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.builder.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// A writer that hashes everything written through it.
+pub struct HashingWriter<W> {
+    inner: W,
+    builder: CidBuilder,
+}
+impl<W: io::Write> HashingWriter<W> {
+    pub fn new(version: u8, inner: W) -> Result<Self, CidError> {
+        Ok(Self {
+            inner,
+            builder: Cid::try_builder(version)?,
+        })
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns the CID of the data written so far, along with the inner
+    /// writer. The inner writer is not flushed.
+    pub fn finalize(self) -> (Cid, W) {
+        (self.builder.finalize(), self.inner)
+    }
+}
+impl<W: io::Write> io::Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.builder.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BLOCK_SIZE;
+
+    #[test]
+    fn builder_as_writer() {
+        let data = crate::test_data(BLOCK_SIZE * 2 + 3);
+        let mut builder = Cid::builder(Cid::VERSION_RAW);
+        io::copy(&mut data.as_slice(), &mut builder).unwrap();
+        assert_eq!(builder.finalize(), Cid::from_data(Cid::VERSION_RAW, &data));
+    }
+
+    #[test]
+    fn tee_adapters() {
+        let data = crate::test_data(BLOCK_SIZE * 2 + 3);
+        let expected = Cid::from_data(Cid::VERSION_RAW, &data);
+
+        let mut reader = HashingReader::new(Cid::VERSION_RAW, data.as_slice()).unwrap();
+        let mut out = Vec::new();
+        io::copy(&mut reader, &mut out).unwrap();
+        assert_eq!(reader.finalize().0, expected);
+        assert_eq!(out, data);
+
+        let mut writer = HashingWriter::new(Cid::VERSION_RAW, Vec::new()).unwrap();
+        io::copy(&mut data.as_slice(), &mut writer).unwrap();
+        let (cid, out) = writer.finalize();
+        assert_eq!(cid, expected);
+        assert_eq!(out, data);
+    }
+}
@@ -1,7 +1,50 @@
+#[cfg(any(feature = "tokio", feature = "futures"))]
+mod async_io;
+mod checkpoint;
 mod cid;
+mod encoding;
+mod hasher;
+mod hashing;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod multiformats;
+mod outboard;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod progress;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod tree;
+mod verify;
+mod version;
 
+/// Block size of the 16 KiB versions, including [`Cid::VERSION_RAW`].
 pub const BLOCK_SIZE: usize = 16 * 1024;
 
 pub type Hash = [u8; 32];
 
-pub use cid::{Cid, CidBuilder, CidDecodeError};
+pub use checkpoint::CheckpointError;
+pub use cid::{Cid, CidBuilder, CidDecodeError, CidError, FileChanged};
+pub use encoding::Encoding;
+pub use hasher::{AnyHasher, CidHasher, HashAlgorithm};
+pub use hashing::{HashingReader, HashingWriter};
+pub use outboard::{Outboard, OutboardDecodeError};
+pub use progress::CancellationToken;
+pub use tree::{BlockProof, MerkleTree, ProofDecodeError, RangeProof, TreeMode};
+pub use verify::VerifyingReader;
+pub use version::CidVersion;
+
+/// Content of `len` bytes whose blocks all differ from each other.
+#[cfg(test)]
+pub(crate) fn test_data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Hashes `data`, keeping the whole Merkle tree.
+#[cfg(test)]
+pub(crate) fn test_tree(version: u8, data: &[u8]) -> (Cid, MerkleTree) {
+    let mut builder = Cid::builder(version).with_tree();
+    builder.update(data);
+    let (cid, tree) = builder.finalize_with_tree();
+    (cid, tree.unwrap())
+}
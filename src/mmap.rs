@@ -0,0 +1,74 @@
+use memmap2::Mmap;
+use std::{fs::File, path::Path, time::SystemTime};
+
+use crate::{Cid, CidError, CidVersion};
+
+impl Cid {
+    /// Opens the file at `path` and hashes it with [`Cid::from_mmap`].
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Cid::from_mmap`].
+    pub unsafe fn from_path(
+        version: u8,
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, SystemTime), CidError> {
+        // SAFETY: forwarded to the caller.
+        unsafe { Self::from_mmap(version, &mut File::open(path)?) }
+    }
+
+    /// Like [`Cid::from_file`], but hashes the file through a memory map
+    /// instead of reading it into a buffer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must guarantee that nothing writes to or truncates the file,
+    /// in this process or any other, while it is being hashed. Concurrent
+    /// writes make the mapped bytes change under a shared slice, and accessing
+    /// pages cut off by truncation terminates the process. The change
+    /// detection performed afterwards only reports such changes; it does not
+    /// make them safe.
+    pub unsafe fn from_mmap(version: u8, file: &mut File) -> Result<(Self, SystemTime), CidError> {
+        if CidVersion::get(version).is_none() {
+            return Err(CidError::UnsupportedVersion { version });
+        }
+        Self::hash_unmodified(file, |file| {
+            if file.metadata()?.len() == 0 {
+                return Ok(Self::from_data(version, []));
+            }
+            // SAFETY: the caller guarantees that the file is neither written
+            // to nor truncated while the map is alive.
+            let map = unsafe { Mmap::map(&*file)? };
+            Ok(Self::from_mapped(version, &map))
+        })
+    }
+
+    #[cfg(feature = "rayon")]
+    fn from_mapped(version: u8, data: &[u8]) -> Self {
+        Self::from_data_par(version, data)
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    fn from_mapped(version: u8, data: &[u8]) -> Self {
+        Self::from_data(version, data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BLOCK_SIZE;
+    use std::io::Write;
+
+    #[test]
+    fn matches_reader() {
+        for len in [0, 5, BLOCK_SIZE * 3 + 1] {
+            let data = crate::test_data(len);
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            file.write_all(&data).unwrap();
+            // SAFETY: the file is not modified while it is being hashed.
+            let (cid, _) = unsafe { Cid::from_path(Cid::VERSION_RAW, file.path()) }.unwrap();
+            assert_eq!(cid, Cid::from_data(Cid::VERSION_RAW, &data));
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use bytes::{Buf, BufMut};
+use bytes_varint::{VarIntSupport, VarIntSupportMut};
+
+use crate::{cid::varint_len, encoding::Encoding, Cid, CidDecodeError};
+
+impl Cid {
+    /// Start of the private-use multihash range. The multihash code of a CID
+    /// is this value with the version byte in its lowest bits.
+    pub const MULTIHASH_CODE_BASE: u64 = 0x300000;
+
+    /// Codec of the CIDv1s produced by [`Cid::to_cidv1`], from the private-use
+    /// range, standing for content hashed into an anys CID.
+    pub const CIDV1_CODEC: u64 = 0x300000;
+
+    pub fn multihash_code(&self) -> u64 {
+        Self::MULTIHASH_CODE_BASE | self.version() as u64
+    }
+
+    /// Encodes the CID as a multihash whose digest is the varint size
+    /// followed by the CID hash.
+    ///
+    /// That hash is the root of the Merkle tree described by the version, not
+    /// a plain SHA-256 or BLAKE3 of the content, so it is tagged with
+    /// [`Cid::multihash_code`] from the private-use range rather than a
+    /// registered code: tools that recompute registered hashes would
+    /// otherwise reject every anys identifier.
+    pub fn to_multihash(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::MAX_SIZE_IN_BYTES + 8);
+        self.encode_multihash(&mut buf);
+        buf
+    }
+
+    pub fn from_multihash(bytes: &[u8]) -> Result<Self, CidDecodeError> {
+        let mut buf = bytes;
+        let cid = Self::decode_multihash(&mut buf)?;
+        if buf.has_remaining() {
+            return Err(CidDecodeError::InvalidMultihash);
+        }
+        Ok(cid)
+    }
+
+    /// Encodes the CID as a binary CIDv1 with codec [`Cid::CIDV1_CODEC`].
+    pub fn to_cidv1(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::MAX_SIZE_IN_BYTES + 16);
+        buf.put_u64_varint(1);
+        buf.put_u64_varint(Self::CIDV1_CODEC);
+        self.encode_multihash(&mut buf);
+        buf
+    }
+
+    pub fn from_cidv1(bytes: &[u8]) -> Result<Self, CidDecodeError> {
+        let mut buf = bytes;
+        if get_varint(&mut buf) != Some(1) || get_varint(&mut buf) != Some(Self::CIDV1_CODEC) {
+            return Err(CidDecodeError::InvalidCidV1);
+        }
+        Self::from_multihash(buf)
+    }
+
+    /// Formats the CID as a base32 CIDv1 string, as used by IPFS.
+    pub fn to_cidv1_string(&self) -> String {
+        Encoding::Base32Lower.encode(&self.to_cidv1())
+    }
+
+    /// Parses a CIDv1 string in any of the multibase encodings of
+    /// [`Encoding`].
+    pub fn from_cidv1_str(s: &str) -> Result<Self, CidDecodeError> {
+        let mut chars = s.chars();
+        let char = chars.next().ok_or(CidDecodeError::Empty)?;
+        let bytes = Encoding::from_prefix(char)
+            .ok_or(CidDecodeError::InvalidCidV1)?
+            .decode(chars.as_str())
+            .ok_or(CidDecodeError::InvalidEncoding)?;
+        Self::from_cidv1(&bytes)
+    }
+
+    fn encode_multihash(&self, buf: &mut impl BufMut) {
+        buf.put_u64_varint(self.multihash_code());
+        buf.put_u64_varint((self.encoded_len() - 1) as u64);
+        buf.put_u64_varint(self.size());
+        buf.put_slice(self.hash());
+    }
+
+    fn decode_multihash(buf: &mut impl Buf) -> Result<Self, CidDecodeError> {
+        let code = get_varint(buf).ok_or(CidDecodeError::InvalidMultihash)?;
+        let version = code
+            .checked_sub(Self::MULTIHASH_CODE_BASE)
+            .and_then(|version| u8::try_from(version).ok())
+            .ok_or(CidDecodeError::InvalidMultihash)?;
+        let len = get_varint(buf)
+            .and_then(|len| usize::try_from(len).ok())
+            .filter(|&len| len <= buf.remaining())
+            .ok_or(CidDecodeError::InvalidMultihash)?;
+        let mut bytes = vec![version];
+        bytes.extend_from_slice(&buf.copy_to_bytes(len));
+        Self::decode_exact(bytes.as_slice(), true)
+    }
+}
+
+/// Reads a varint in its minimal encoding, as multiformats require.
+fn get_varint(buf: &mut impl Buf) -> Option<u64> {
+    let remaining = buf.remaining();
+    let value = buf.try_get_u64_varint().ok()?;
+    (remaining - buf.remaining() == varint_len(value)).then_some(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cid = Cid::from_data(Cid::VERSION_BLAKE3, b"hello");
+        let multihash = cid.to_multihash();
+        assert_eq!(&multihash[..4], &[0xc3, 0x80, 0xc0, 0x01]);
+        assert_eq!(multihash[4] as usize, cid.encoded_len() - 1);
+        assert_eq!(Cid::from_multihash(&multihash).unwrap(), cid);
+
+        let s = cid.to_cidv1_string();
+        assert!(s.starts_with('b'));
+        assert_eq!(Cid::from_cidv1_str(&s).unwrap(), cid);
+        assert_eq!(Cid::from_cidv1(&cid.to_cidv1()).unwrap(), cid);
+    }
+
+    #[test]
+    fn rejects_foreign() {
+        let mut sha256 = vec![0x12, 0x20];
+        sha256.extend([0; 32]);
+        assert!(matches!(
+            Cid::from_multihash(&sha256),
+            Err(CidDecodeError::InvalidMultihash)
+        ));
+
+        let mut cidv1 = vec![0x01, 0x55];
+        cidv1.extend(sha256);
+        assert!(matches!(
+            Cid::from_cidv1(&cidv1),
+            Err(CidDecodeError::InvalidCidV1)
+        ));
+
+        let mut truncated = Cid::from_data(Cid::VERSION_RAW, b"").to_multihash();
+        truncated.pop();
+        assert!(Cid::from_multihash(&truncated).is_err());
+    }
+}
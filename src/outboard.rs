@@ -0,0 +1,190 @@
+use bytes::{Buf, BufMut};
+use std::mem;
+use thiserror::Error;
+
+use crate::{tree::MerkleTree, Cid, CidDecodeError, Hash};
+
+#[derive(Error, Debug)]
+pub enum OutboardDecodeError {
+    #[error("invalid magic")]
+    InvalidMagic,
+
+    #[error("unsupported outboard version: {version}")]
+    UnsupportedVersion { version: u8 },
+
+    #[error("invalid cid: {0}")]
+    Cid(#[from] CidDecodeError),
+
+    #[error("invalid length")]
+    InvalidLength,
+
+    #[error("tree root does not match cid")]
+    RootMismatch,
+
+    #[error("tree nodes do not match leaves")]
+    NodeMismatch,
+}
+
+/// A [`Cid`] stored together with every hash of its Merkle tree, so that
+/// proofs can be served without re-reading the content.
+///
+/// The encoding is:
+///
+/// - the magic bytes `ANYO` and a format version byte;
+/// - the length of the encoded CID as a single byte, followed by the CID;
+/// - every node of the tree in heap order, padding included.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outboard {
+    cid: Cid,
+    tree: MerkleTree,
+}
+impl Outboard {
+    pub const MAGIC: [u8; 4] = *b"ANYO";
+
+    pub const VERSION: u8 = 1;
+
+    /// Pairs a CID with its tree. Returns `None` if the tree is not the one
+    /// the CID was computed from.
+    pub fn new(cid: Cid, tree: MerkleTree) -> Option<Self> {
+        if tree.version() != cid.version()
+            || tree.num_leaves() as u64 != cid.num_blocks()
+            || !cid.matches_root(tree.root())
+        {
+            return None;
+        }
+        Some(Self { cid, tree })
+    }
+
+    pub fn cid(&self) -> &Cid {
+        &self.cid
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    pub fn into_parts(self) -> (Cid, MerkleTree) {
+        (self.cid, self.tree)
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        Self::MAGIC.len() + 2 + self.cid.encoded_len() + mem::size_of_val(self.tree.nodes())
+    }
+
+    pub fn encode(&self, buf: &mut impl BufMut) {
+        let cid = self.cid.to_bytes();
+        buf.put_slice(&Self::MAGIC);
+        buf.put_u8(Self::VERSION);
+        buf.put_u8(cid.len() as u8);
+        buf.put_slice(&cid);
+        for node in self.tree.nodes() {
+            buf.put_slice(node);
+        }
+    }
+
+    /// Decodes an outboard, checking that the stored tree root matches the
+    /// CID and that every other node, padding included, is the one computed
+    /// from the stored leaves.
+    pub fn decode(mut buf: impl Buf) -> Result<Self, OutboardDecodeError> {
+        if buf.remaining() < Self::MAGIC.len() + 2 {
+            return Err(OutboardDecodeError::InvalidLength);
+        }
+        let mut magic = [0; 4];
+        buf.copy_to_slice(&mut magic);
+        if magic != Self::MAGIC {
+            return Err(OutboardDecodeError::InvalidMagic);
+        }
+        let version = buf.get_u8();
+        if version != Self::VERSION {
+            return Err(OutboardDecodeError::UnsupportedVersion { version });
+        }
+        let cid_len = buf.get_u8() as usize;
+        if buf.remaining() < cid_len {
+            return Err(OutboardDecodeError::InvalidLength);
+        }
+        let cid = Cid::decode(buf.copy_to_bytes(cid_len))?;
+
+        let num_leaves =
+            usize::try_from(cid.num_blocks()).map_err(|_| OutboardDecodeError::InvalidLength)?;
+        let num_nodes =
+            MerkleTree::num_nodes(num_leaves).ok_or(OutboardDecodeError::InvalidLength)?;
+        if Some(buf.remaining()) != num_nodes.checked_mul(mem::size_of::<Hash>()) {
+            return Err(OutboardDecodeError::InvalidLength);
+        }
+        let nodes = (0..num_nodes)
+            .map(|_| {
+                let mut hash = Hash::default();
+                buf.copy_to_slice(&mut hash);
+                hash
+            })
+            .collect();
+        let tree = MerkleTree::from_nodes(cid.version(), num_leaves, nodes)
+            .ok_or(OutboardDecodeError::InvalidLength)?;
+        let outboard = Self::new(cid, tree).ok_or(OutboardDecodeError::RootMismatch)?;
+        let rebuilt = MerkleTree::from_leaves(outboard.cid.version(), outboard.tree.leaves());
+        if rebuilt.nodes() != outboard.tree.nodes() {
+            return Err(OutboardDecodeError::NodeMismatch);
+        }
+        Ok(outboard)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.encode(&mut buf);
+        buf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BLOCK_SIZE;
+
+    fn outboard(len: usize) -> Outboard {
+        let data = crate::test_data(len);
+        let (cid, tree) = crate::test_tree(Cid::VERSION_RAW, &data);
+        Outboard::new(cid, tree).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for len in [0, 10, BLOCK_SIZE * 5 + 1] {
+            let outboard = outboard(len);
+            let bytes = outboard.to_bytes();
+            assert_eq!(bytes.len(), outboard.encoded_len());
+            assert_eq!(Outboard::decode(bytes.as_slice()).unwrap(), outboard);
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_root() {
+        let mut bytes = outboard(BLOCK_SIZE * 2).to_bytes();
+        let cid_len = bytes[5] as usize;
+        bytes[6 + cid_len] ^= 1;
+        assert!(matches!(
+            Outboard::decode(bytes.as_slice()),
+            Err(OutboardDecodeError::RootMismatch)
+        ));
+        bytes.pop();
+        assert!(matches!(
+            Outboard::decode(bytes.as_slice()),
+            Err(OutboardDecodeError::InvalidLength)
+        ));
+    }
+
+    #[test]
+    fn rejects_tampered_nodes() {
+        let bytes = outboard(BLOCK_SIZE * 5).to_bytes();
+        let nodes = 6 + bytes[5] as usize;
+        let hash_len = mem::size_of::<Hash>();
+        // An interior node, a leaf and a padding slot of the 8-leaf tree.
+        for node in [1, 7, 14] {
+            let mut bytes = bytes.clone();
+            bytes[nodes + node * hash_len] ^= 1;
+            assert!(matches!(
+                Outboard::decode(bytes.as_slice()),
+                Err(OutboardDecodeError::NodeMismatch)
+            ));
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use rayon::prelude::*;
+use std::{cmp, io};
+
+use crate::{hasher::CidHasher, tree::TreeHasher, Cid, CidBuilder, CidError, CidVersion, Hash};
+
+/// Number of bytes [`Cid::from_reader_par`] reads before hashing them, rounded
+/// down to whole blocks.
+const BATCH_BYTES: usize = 8 << 20;
+
+/// Subtrees with at most this many leaves are built on a single thread.
+const SEQUENTIAL_LEAVES: usize = 64;
+
+impl<H: CidHasher + Send + Sync> CidBuilder<H> {
+    /// Like [`CidBuilder::update`], but hashes whole blocks in parallel on the
+    /// rayon thread pool.
+    pub fn update_par(&mut self, data: impl AsRef<[u8]>) {
+        let data = data.as_ref();
+        let (head, data) = data.split_at(cmp::min(self.block_remaining(), data.len()));
+        self.update(head);
+        if self.head != 0 {
+            return;
+        }
+
+        let block_size = self.block_size();
+        let (blocks, tail) = data.split_at(data.len() / block_size * block_size);
+        let tree = self.tree();
+        let leaves: Vec<Hash> = blocks
+            .par_chunks(block_size)
+            .map(|block| tree.hash_leaf(block))
+            .collect();
+        self.push_leaves(leaves, blocks.len() as u64);
+        self.update(tail);
+    }
+}
+
+impl Cid {
+    /// Like [`Cid::from_data`], but hashes blocks and builds the tree in
+    /// parallel.
+    pub fn from_data_par(version: u8, data: impl AsRef<[u8]>) -> Cid {
+        let data = data.as_ref();
+        let version = CidVersion::get(version).expect("unsupported cid version");
+        let tree = version.tree_hasher();
+        let leaves: Vec<Hash> = data
+            .par_chunks(version.block_size())
+            .map(|block| tree.hash_leaf(block))
+            .collect();
+        let root = root_par(&tree, &leaves);
+        Cid::from_root(version, &tree, data.len() as u64, &root)
+    }
+
+    /// Like [`Cid::from_reader`], but hashes blocks in parallel.
+    pub fn from_reader_par(version: u8, mut reader: impl io::Read) -> Result<Self, CidError> {
+        let mut builder = Self::try_builder(version)?;
+        let block_size = builder.block_size();
+        let mut buf = vec![0; cmp::max(BATCH_BYTES / block_size, 1) * block_size];
+        loop {
+            let mut len = 0;
+            while len < buf.len() {
+                match reader.read(&mut buf[len..]) {
+                    Ok(0) => break,
+                    Ok(n) => len += n,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            builder.update_par(&buf[..len]);
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(builder.finalize())
+    }
+}
+
+/// Computes the root of the tree over `leaves`, splitting subtrees across
+/// threads.
+pub(crate) fn root_par<H: CidHasher + Send + Sync>(tree: &TreeHasher<H>, leaves: &[Hash]) -> Hash {
+    let height = leaves.len().next_power_of_two().trailing_zeros() as usize;
+    let mut paddings = vec![tree.padding()];
+    for i in 0..height {
+        paddings.push(tree.hash_node(&paddings[i], &paddings[i]));
+    }
+    subtree_root(tree, &paddings, leaves, height)
+}
+
+fn subtree_root<H: CidHasher + Send + Sync>(
+    tree: &TreeHasher<H>,
+    paddings: &[Hash],
+    leaves: &[Hash],
+    height: usize,
+) -> Hash {
+    if leaves.is_empty() {
+        return paddings[height];
+    }
+    if height == 0 {
+        return leaves[0];
+    }
+    let (left, right) = leaves.split_at(cmp::min(leaves.len(), 1 << (height - 1)));
+    let (left, right) = if leaves.len() <= SEQUENTIAL_LEAVES {
+        (
+            subtree_root(tree, paddings, left, height - 1),
+            subtree_root(tree, paddings, right, height - 1),
+        )
+    } else {
+        rayon::join(
+            || subtree_root(tree, paddings, left, height - 1),
+            || subtree_root(tree, paddings, right, height - 1),
+        )
+    };
+    tree.hash_node(&left, &right)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BLOCK_SIZE;
+
+    #[test]
+    fn matches_sequential() {
+        for len in [0, 1, BLOCK_SIZE, BLOCK_SIZE * 100 + 17, BLOCK_SIZE * 600] {
+            let data = crate::test_data(len);
+            for version in [Cid::VERSION_RAW, Cid::VERSION_BLAKE3] {
+                let expected = Cid::from_data(version, &data);
+                assert_eq!(Cid::from_data_par(version, &data), expected);
+                assert_eq!(
+                    Cid::from_reader_par(version, data.as_slice()).unwrap(),
+                    expected
+                );
+
+                let mut builder = Cid::builder(version);
+                builder.update_par(&data[..len / 3]);
+                builder.update_par(&data[len / 3..]);
+                assert_eq!(builder.finalize(), expected);
+
+                let (a, b) = (cmp::min(3, len), cmp::min(5, len));
+                let mut builder = Cid::builder(version);
+                builder.update(&data[..a]);
+                builder.update_par(&data[a..b]);
+                builder.update_par(&data[b..]);
+                assert_eq!(builder.finalize(), expected);
+            }
+        }
+    }
+}
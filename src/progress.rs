@@ -0,0 +1,107 @@
+use std::{
+    fs::File,
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use crate::{Cid, CidError};
+
+/// A flag shared between a hashing operation and whoever may want to stop it.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Cid {
+    /// Like [`Cid::from_reader`], but calls `progress` with the number of
+    /// bytes hashed so far and `total` as reading goes, and stops with
+    /// [`CidError::Cancelled`] once `cancel` is triggered.
+    pub fn from_reader_with_progress(
+        version: u8,
+        reader: impl io::Read,
+        total: Option<u64>,
+        mut progress: impl FnMut(u64, Option<u64>),
+        cancel: &CancellationToken,
+    ) -> Result<Self, CidError> {
+        Self::from_reader_inspect(version, reader, |hashed| {
+            if cancel.is_cancelled() {
+                return Err(CidError::Cancelled);
+            }
+            progress(hashed, total);
+            Ok(())
+        })
+    }
+
+    /// Like [`Cid::from_file`], but reports progress and can be cancelled as
+    /// [`Cid::from_reader_with_progress`] does. The total is taken from the
+    /// file metadata.
+    pub fn from_file_with_progress(
+        version: u8,
+        file: &mut File,
+        progress: impl FnMut(u64, Option<u64>),
+        cancel: &CancellationToken,
+    ) -> Result<(Self, SystemTime), CidError> {
+        let total = file.metadata()?.len();
+        Self::hash_unmodified(file, |file| {
+            Self::from_reader_with_progress(version, file, Some(total), progress, cancel)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BLOCK_SIZE;
+
+    #[test]
+    fn reports_progress() {
+        let data = vec![1; BLOCK_SIZE * 4 + 2];
+        let mut reports = Vec::new();
+        let cid = Cid::from_reader_with_progress(
+            Cid::VERSION_RAW,
+            data.as_slice(),
+            Some(data.len() as u64),
+            |hashed, total| reports.push((hashed, total)),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(cid, Cid::from_data(Cid::VERSION_RAW, &data));
+        assert_eq!(reports.first(), Some(&(0, Some(data.len() as u64))));
+        assert_eq!(reports.last().unwrap().0, data.len() as u64);
+        assert!(reports.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
+
+    #[test]
+    fn cancels() {
+        let data = vec![1; BLOCK_SIZE * 4];
+        let cancel = CancellationToken::new();
+        let err = Cid::from_reader_with_progress(
+            Cid::VERSION_RAW,
+            data.as_slice(),
+            None,
+            |hashed, _| {
+                if hashed >= BLOCK_SIZE as u64 {
+                    cancel.cancel();
+                }
+            },
+            &cancel.clone(),
+        )
+        .unwrap_err();
+        assert!(matches!(err, CidError::Cancelled));
+    }
+}
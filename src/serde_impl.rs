@@ -0,0 +1,65 @@
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::fmt;
+
+use crate::Cid;
+
+/// CIDs are serialized as their base58 string in human-readable formats, and
+/// as the bytes written by [`Cid::encode`] otherwise.
+impl Serialize for Cid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Cid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(CidVisitor)
+        } else {
+            deserializer.deserialize_bytes(CidVisitor)
+        }
+    }
+}
+
+struct CidVisitor;
+impl Visitor<'_> for CidVisitor {
+    type Value = Cid;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a cid string or its encoded bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Cid::from_bytes(v).map_err(E::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let cid = Cid::from_data(Cid::VERSION_BLAKE3, b"hello");
+
+        let json = serde_json::to_string(&cid).unwrap();
+        assert_eq!(json, format!("\"{cid}\""));
+        assert_eq!(serde_json::from_str::<Cid>(&json).unwrap(), cid);
+        assert!(serde_json::from_str::<Cid>("\"A\"").is_err());
+
+        let bytes = bincode::serialize(&cid).unwrap();
+        assert!(bytes.ends_with(&cid.to_bytes()));
+        assert_eq!(bincode::deserialize::<Cid>(&bytes).unwrap(), cid);
+    }
+}
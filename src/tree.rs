@@ -0,0 +1,576 @@
+use bytes::{Buf, BufMut};
+use std::{
+    fmt::{self, Debug},
+    mem,
+    ops::Range,
+};
+use thiserror::Error;
+
+use crate::{
+    hasher::{AnyHasher, CidHasher},
+    CidVersion, Hash,
+};
+
+#[derive(Error, Debug)]
+pub enum ProofDecodeError {
+    #[error("proof too deep: {depth}")]
+    TooDeep { depth: u8 },
+
+    #[error("too many hashes: {count}")]
+    TooManyHashes { count: u8 },
+
+    #[error("invalid length")]
+    InvalidLength,
+}
+
+/// How leaves, interior nodes and padding of the tree are hashed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TreeMode {
+    /// Used by [`Cid::VERSION_RAW`](crate::Cid::VERSION_RAW): bare hashes of blocks and of concatenated
+    /// children, with zero padding and the tree root used as the CID hash.
+    Plain,
+
+    /// Every hash input is prefixed with a tag telling leaves, interior nodes
+    /// and padding apart, and the content size is bound into the CID hash.
+    Tagged,
+}
+impl TreeMode {
+    const LEAF_TAG: u8 = 0;
+    const NODE_TAG: u8 = 1;
+    const PADDING_TAG: u8 = 2;
+    const ROOT_TAG: u8 = 3;
+}
+
+/// Hashes the leaves and nodes of a tree with the hash function `H`.
+#[derive(Clone)]
+pub(crate) struct TreeHasher<H = AnyHasher> {
+    mode: TreeMode,
+    hasher: H,
+}
+impl<H: CidHasher> TreeHasher<H> {
+    /// `hasher` must not have been fed any data.
+    pub fn new(mode: TreeMode, hasher: H) -> Self {
+        Self { mode, hasher }
+    }
+
+    /// Returns a hasher ready to be fed the content of a block.
+    pub fn leaf_hasher(&self) -> H {
+        let mut hasher = self.hasher.clone();
+        if self.mode == TreeMode::Tagged {
+            hasher.update(&[TreeMode::LEAF_TAG]);
+        }
+        hasher
+    }
+
+    pub fn hash_leaf(&self, data: &[u8]) -> Hash {
+        let mut hasher = self.leaf_hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    pub fn hash_node(&self, left: &Hash, right: &Hash) -> Hash {
+        let mut hasher = self.hasher.clone();
+        if self.mode == TreeMode::Tagged {
+            hasher.update(&[TreeMode::NODE_TAG]);
+        }
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize()
+    }
+
+    pub fn padding(&self) -> Hash {
+        match self.mode {
+            TreeMode::Plain => Hash::default(),
+            TreeMode::Tagged => {
+                let mut hasher = self.hasher.clone();
+                hasher.update(&[TreeMode::PADDING_TAG]);
+                hasher.finalize()
+            }
+        }
+    }
+
+    /// Turns the root of the tree into the hash stored in the CID.
+    pub fn finish(&self, root: &Hash, size: u64) -> Hash {
+        match self.mode {
+            TreeMode::Plain => *root,
+            TreeMode::Tagged => {
+                let mut hasher = self.hasher.clone();
+                hasher.update(&[TreeMode::ROOT_TAG]);
+                hasher.update(&size.to_le_bytes());
+                hasher.update(root);
+                hasher.finalize()
+            }
+        }
+    }
+}
+
+/// Roots of the complete subtrees seen so far, folded together as leaves
+/// arrive so that only a logarithmic number of hashes is held at any time.
+#[derive(Clone, Default)]
+pub(crate) struct SubtreeStack {
+    /// `(height, root)` pairs with strictly decreasing heights.
+    stack: Vec<(u32, Hash)>,
+}
+impl SubtreeStack {
+    pub fn push<H: CidHasher>(&mut self, tree: &TreeHasher<H>, leaf: Hash) {
+        let (mut height, mut node) = (0, leaf);
+        while let Some(&(top, left)) = self.stack.last() {
+            if top != height {
+                break;
+            }
+            self.stack.pop();
+            node = tree.hash_node(&left, &node);
+            height += 1;
+        }
+        self.stack.push((height, node));
+    }
+
+    /// Rebuilds the stack after `num_leaves` leaves from the subtree roots
+    /// returned by [`SubtreeStack::roots`].
+    pub fn from_roots(num_leaves: u64, roots: Vec<Hash>) -> Option<Self> {
+        if roots.len() != num_leaves.count_ones() as usize {
+            return None;
+        }
+        let heights = (0..u64::BITS).rev().filter(|h| num_leaves >> h & 1 == 1);
+        Some(Self {
+            stack: heights.zip(roots).collect(),
+        })
+    }
+
+    /// Roots of the complete subtrees, from the largest to the smallest.
+    pub fn roots(&self) -> impl ExactSizeIterator<Item = &Hash> {
+        self.stack.iter().map(|(_, root)| root)
+    }
+
+    /// Returns the root of the tree over all pushed leaves, padded to a power
+    /// of two as in [`MerkleTree`].
+    pub fn root<H: CidHasher>(&self, tree: &TreeHasher<H>) -> Hash {
+        let mut subtrees = self.stack.iter().rev();
+        let Some(&(mut height, mut root)) = subtrees.next() else {
+            return tree.padding();
+        };
+        let (mut padding, mut padding_height) = (tree.padding(), 0);
+        for &(left_height, left) in subtrees {
+            while height < left_height {
+                while padding_height < height {
+                    padding = tree.hash_node(&padding, &padding);
+                    padding_height += 1;
+                }
+                root = tree.hash_node(&root, &padding);
+                height += 1;
+            }
+            root = tree.hash_node(&left, &root);
+            height += 1;
+        }
+        root
+    }
+}
+
+/// A complete Merkle tree over the leaf hashes of a [`Cid`](crate::Cid).
+///
+/// Nodes are stored in heap order: the root lives at index 0 and the children
+/// of node `i` are at `2i + 1` and `2i + 2`. Leaves are padded up to the next
+/// power of two, exactly as the hash of a `Cid` is computed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct MerkleTree {
+    version: u8,
+    num_leaves: usize,
+    nodes: Vec<Hash>,
+}
+impl MerkleTree {
+    /// Builds the tree of a CID of the given version from its leaf hashes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the version is not supported.
+    pub fn from_leaves(version: u8, leaves: &[Hash]) -> Self {
+        let tree = CidVersion::get(version)
+            .expect("unsupported cid version")
+            .tree_hasher();
+        Self::build(version, &tree, leaves)
+    }
+
+    pub(crate) fn build<H: CidHasher>(version: u8, tree: &TreeHasher<H>, leaves: &[Hash]) -> Self {
+        let size = leaves.len().next_power_of_two();
+        let mut nodes = Vec::with_capacity(size * 2 - 1);
+        nodes.resize_with(size - 1, Hash::default);
+        nodes.extend_from_slice(leaves);
+        nodes.resize(size * 2 - 1, tree.padding());
+        for i in (0..size - 1).rev() {
+            nodes[i] = tree.hash_node(&nodes[i * 2 + 1], &nodes[i * 2 + 2]);
+        }
+        Self {
+            version,
+            num_leaves: leaves.len(),
+            nodes,
+        }
+    }
+
+    /// Rebuilds a tree from the nodes in heap order, as returned by
+    /// [`MerkleTree::nodes`]. Returns `None` if the node count does not fit
+    /// `num_leaves`.
+    pub(crate) fn from_nodes(version: u8, num_leaves: usize, nodes: Vec<Hash>) -> Option<Self> {
+        if Self::num_nodes(num_leaves) != Some(nodes.len()) {
+            return None;
+        }
+        Some(Self {
+            version,
+            num_leaves,
+            nodes,
+        })
+    }
+
+    /// Number of nodes in a tree over `num_leaves` leaves, padding included,
+    /// or `None` if it does not fit in a `usize`.
+    pub(crate) fn num_nodes(num_leaves: usize) -> Option<usize> {
+        let width = num_leaves.checked_next_power_of_two()?;
+        Some(width.checked_mul(2)? - 1)
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The root node of the tree. For versions other than
+    /// [`Cid::VERSION_RAW`](crate::Cid::VERSION_RAW), the CID hash is derived from it together with the
+    /// content size rather than being equal to it.
+    pub fn root(&self) -> &Hash {
+        &self.nodes[0]
+    }
+
+    /// All nodes of the tree in heap order, including padding.
+    pub fn nodes(&self) -> &[Hash] {
+        &self.nodes
+    }
+
+    pub fn num_leaves(&self) -> usize {
+        self.num_leaves
+    }
+
+    pub fn leaves(&self) -> &[Hash] {
+        let start = self.nodes.len() / 2;
+        &self.nodes[start..start + self.num_leaves]
+    }
+
+    /// Returns the inclusion proof of the leaf at `index`, or `None` if the
+    /// index is out of range.
+    pub fn proof(&self, index: usize) -> Option<BlockProof> {
+        if index >= self.num_leaves {
+            return None;
+        }
+        let mut node = self.nodes.len() / 2 + index;
+        let mut siblings = Vec::new();
+        while node != 0 {
+            let sibling = if node % 2 == 1 { node + 1 } else { node - 1 };
+            siblings.push(self.nodes[sibling]);
+            node = (node - 1) / 2;
+        }
+        Some(BlockProof { siblings })
+    }
+
+    /// Returns a proof covering the contiguous span of leaves in `range`, or
+    /// `None` if the range is empty or out of bounds.
+    pub fn range_proof(&self, range: Range<usize>) -> Option<RangeProof> {
+        if range.is_empty() || range.end > self.num_leaves {
+            return None;
+        }
+        let (mut lo, mut hi) = (range.start, range.end);
+        let mut width = self.nodes.len() / 2 + 1;
+        let mut hashes = Vec::new();
+        while width > 1 {
+            let base = width - 1;
+            if lo % 2 == 1 {
+                hashes.push(self.nodes[base + lo - 1]);
+            }
+            if hi % 2 == 1 {
+                hashes.push(self.nodes[base + hi]);
+            }
+            lo /= 2;
+            hi = hi.div_ceil(2);
+            width /= 2;
+        }
+        Some(RangeProof { hashes })
+    }
+}
+impl Debug for MerkleTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MerkleTree")
+            .field("num_leaves", &self.num_leaves)
+            .field("root", &hex::encode(self.root()))
+            .finish()
+    }
+}
+
+/// Sibling hashes on the path from a leaf up to the root, ordered from the
+/// bottom of the tree to the top.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BlockProof {
+    siblings: Vec<Hash>,
+}
+impl BlockProof {
+    /// A tree over `u64` blocks is never deeper than this.
+    pub const MAX_DEPTH: usize = 64;
+
+    pub fn siblings(&self) -> &[Hash] {
+        &self.siblings
+    }
+
+    pub fn encode(&self, buf: &mut impl BufMut) {
+        buf.put_u8(self.siblings.len() as u8);
+        for sibling in &self.siblings {
+            buf.put_slice(sibling);
+        }
+    }
+
+    pub fn decode(mut buf: impl Buf) -> Result<Self, ProofDecodeError> {
+        let depth = buf
+            .try_get_u8()
+            .map_err(|_| ProofDecodeError::InvalidLength)?;
+        if depth as usize > Self::MAX_DEPTH {
+            return Err(ProofDecodeError::TooDeep { depth });
+        }
+        if buf.remaining() != depth as usize * mem::size_of::<Hash>() {
+            return Err(ProofDecodeError::InvalidLength);
+        }
+        let siblings = (0..depth)
+            .map(|_| {
+                let mut hash = Hash::default();
+                buf.copy_to_slice(&mut hash);
+                hash
+            })
+            .collect();
+        Ok(Self { siblings })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + self.siblings.len() * mem::size_of::<Hash>());
+        self.encode(&mut buf);
+        buf
+    }
+
+    /// Folds `leaf` at `index` up through the siblings and returns the
+    /// resulting root.
+    pub(crate) fn root_of<H: CidHasher>(
+        &self,
+        tree: &TreeHasher<H>,
+        mut index: u64,
+        leaf: Hash,
+    ) -> Hash {
+        let mut hash = leaf;
+        for sibling in &self.siblings {
+            hash = if index.is_multiple_of(2) {
+                tree.hash_node(&hash, sibling)
+            } else {
+                tree.hash_node(sibling, &hash)
+            };
+            index /= 2;
+        }
+        hash
+    }
+}
+impl Debug for BlockProof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.siblings.iter().map(hex::encode))
+            .finish()
+    }
+}
+
+/// Hashes needed to recompute the root from a contiguous span of leaves.
+///
+/// Going up the tree level by level, the proof holds the left neighbour of the
+/// span if it starts at a right child, followed by the right neighbour if it
+/// ends at a left child.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RangeProof {
+    hashes: Vec<Hash>,
+}
+impl RangeProof {
+    pub const MAX_HASHES: usize = BlockProof::MAX_DEPTH * 2;
+
+    pub fn hashes(&self) -> &[Hash] {
+        &self.hashes
+    }
+
+    pub fn encode(&self, buf: &mut impl BufMut) {
+        buf.put_u8(self.hashes.len() as u8);
+        for hash in &self.hashes {
+            buf.put_slice(hash);
+        }
+    }
+
+    pub fn decode(mut buf: impl Buf) -> Result<Self, ProofDecodeError> {
+        let count = buf
+            .try_get_u8()
+            .map_err(|_| ProofDecodeError::InvalidLength)?;
+        if count as usize > Self::MAX_HASHES {
+            return Err(ProofDecodeError::TooManyHashes { count });
+        }
+        if buf.remaining() != count as usize * mem::size_of::<Hash>() {
+            return Err(ProofDecodeError::InvalidLength);
+        }
+        let hashes = (0..count)
+            .map(|_| {
+                let mut hash = Hash::default();
+                buf.copy_to_slice(&mut hash);
+                hash
+            })
+            .collect();
+        Ok(Self { hashes })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(1 + self.hashes.len() * mem::size_of::<Hash>());
+        self.encode(&mut buf);
+        buf
+    }
+
+    /// Recomputes the root of a tree `width` leaves wide (a power of two) from
+    /// `leaves` starting at `start`. Returns `None` if the proof does not have
+    /// exactly the hashes required.
+    pub(crate) fn root_of<H: CidHasher>(
+        &self,
+        tree: &TreeHasher<H>,
+        start: u64,
+        mut leaves: Vec<Hash>,
+        mut width: u64,
+    ) -> Option<Hash> {
+        let mut hashes = self.hashes.iter();
+        let mut lo = start;
+        while width > 1 {
+            if lo % 2 == 1 {
+                leaves.insert(0, *hashes.next()?);
+                lo -= 1;
+            }
+            if leaves.len() % 2 == 1 {
+                leaves.push(*hashes.next()?);
+            }
+            leaves = leaves
+                .chunks(2)
+                .map(|pair| tree.hash_node(&pair[0], &pair[1]))
+                .collect();
+            lo /= 2;
+            width /= 2;
+        }
+        if hashes.next().is_some() || leaves.len() != 1 {
+            return None;
+        }
+        Some(leaves[0])
+    }
+}
+impl Debug for RangeProof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.hashes.iter().map(hex::encode))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Cid, BLOCK_SIZE};
+
+    #[test]
+    fn tree_matches_cid() {
+        let data = vec![7; BLOCK_SIZE * 5 + 3];
+        let (cid, tree) = crate::test_tree(Cid::VERSION_RAW, &data);
+        assert_eq!(cid, Cid::from_data(Cid::VERSION_RAW, &data));
+        assert_eq!(tree.root(), cid.hash());
+        assert_eq!(tree.num_leaves() as u64, cid.num_blocks());
+    }
+
+    #[test]
+    fn stack_matches_tree() {
+        for version in [Cid::VERSION_RAW, Cid::VERSION_TAGGED] {
+            let tree = CidVersion::get(version).unwrap().tree_hasher();
+            let mut stack = SubtreeStack::default();
+            let mut leaves = Vec::new();
+            for i in 0..40 {
+                assert_eq!(
+                    &stack.root(&tree),
+                    MerkleTree::from_leaves(version, &leaves).root()
+                );
+                let leaf = [i; 32];
+                stack.push(&tree, leaf);
+                leaves.push(leaf);
+            }
+        }
+    }
+
+    #[test]
+    fn proof_walks_to_root() {
+        let leaves: Vec<Hash> = (0..6).map(|i| [i; 32]).collect();
+        let tree = MerkleTree::from_leaves(Cid::VERSION_RAW, &leaves);
+        let hasher = CidVersion::get(Cid::VERSION_RAW).unwrap().tree_hasher();
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof(index).unwrap();
+            assert_eq!(proof.siblings().len(), 3);
+            let mut hash = *leaf;
+            let mut index = index;
+            for sibling in proof.siblings() {
+                hash = if index.is_multiple_of(2) {
+                    hasher.hash_node(&hash, sibling)
+                } else {
+                    hasher.hash_node(sibling, &hash)
+                };
+                index /= 2;
+            }
+            assert_eq!(&hash, tree.root());
+        }
+        assert!(tree.proof(6).is_none());
+    }
+
+    #[test]
+    fn verify_block() {
+        let data = crate::test_data(BLOCK_SIZE * 3 + 100);
+        let (cid, tree) = crate::test_tree(Cid::VERSION_RAW, &data);
+        for (index, block) in data.chunks(BLOCK_SIZE).enumerate() {
+            let proof = tree.proof(index).unwrap();
+            let proof = BlockProof::decode(proof.to_bytes().as_slice()).unwrap();
+            assert!(cid.verify_block(index as u64, block, &proof));
+            assert!(!cid.verify_block(index as u64 ^ 1, block, &proof));
+        }
+        let proof = tree.proof(0).unwrap();
+        let mut block = data[..BLOCK_SIZE].to_vec();
+        block[42] ^= 1;
+        assert!(!cid.verify_block(0, &block, &proof));
+        assert!(!cid.verify_block(0, &block[..BLOCK_SIZE - 1], &proof));
+    }
+
+    #[test]
+    fn tagged_proofs() {
+        let data = crate::test_data(BLOCK_SIZE * 5 + 9);
+        let (cid, tree) = crate::test_tree(Cid::VERSION_TAGGED, &data);
+        assert_ne!(tree.root(), cid.hash());
+        let proof = tree.proof(5).unwrap();
+        assert!(cid.verify_block(5, &data[BLOCK_SIZE * 5..], &proof));
+        let proof = tree.range_proof(1..4).unwrap();
+        assert!(cid.verify_range(1, &data[BLOCK_SIZE..BLOCK_SIZE * 4], &proof));
+
+        let raw = Cid::new(Cid::VERSION_RAW, cid.size(), *tree.root());
+        assert!(!raw.verify_range(1, &data[BLOCK_SIZE..BLOCK_SIZE * 4], &proof));
+    }
+
+    #[test]
+    fn verify_range() {
+        let data = crate::test_data(BLOCK_SIZE * 11 + 5);
+        let (cid, tree) = crate::test_tree(Cid::VERSION_RAW, &data);
+        for start in 0..12 {
+            for end in start + 1..=12 {
+                let proof = tree.range_proof(start..end).unwrap();
+                let proof = RangeProof::decode(proof.to_bytes().as_slice()).unwrap();
+                let span = &data[start * BLOCK_SIZE..std::cmp::min(end * BLOCK_SIZE, data.len())];
+                assert!(cid.verify_range(start as u64, span, &proof));
+                assert!(!cid.verify_range(start as u64, &span[1..], &proof));
+            }
+        }
+        let proof = tree.range_proof(2..5).unwrap();
+        let mut span = data[BLOCK_SIZE * 2..BLOCK_SIZE * 5].to_vec();
+        assert!(!cid.verify_range(3, &span, &proof));
+        span[BLOCK_SIZE] ^= 1;
+        assert!(!cid.verify_range(2, &span, &proof));
+        assert!(tree.range_proof(3..3).is_none());
+        assert!(tree.range_proof(3..13).is_none());
+    }
+}
@@ -0,0 +1,165 @@
+use std::{io, mem};
+
+use crate::{
+    tree::{MerkleTree, TreeHasher},
+    Cid, Hash,
+};
+
+/// A reader that checks every block of the underlying content against a
+/// [`Cid`] before handing any of its bytes out.
+///
+/// Reads fail with [`io::ErrorKind::InvalidData`] as soon as a block does not
+/// match its leaf hash, so callers never observe unverified data. Once a read
+/// has failed, every later read fails with the same error.
+pub struct VerifyingReader<R> {
+    inner: R,
+    cid: Cid,
+    tree: TreeHasher,
+    leaves: Vec<Hash>,
+    index: u64,
+    block: Vec<u8>,
+    pos: usize,
+    failed: Option<(io::ErrorKind, String)>,
+}
+impl<R: io::Read> VerifyingReader<R> {
+    /// Creates a reader from the leaf hashes of `cid`, which are checked
+    /// against its root up front.
+    pub fn new(inner: R, cid: Cid, leaves: Vec<Hash>) -> io::Result<Self> {
+        let tree = cid.tree_hasher();
+        if leaves.len() as u64 != cid.num_blocks()
+            || !cid.matches_root(MerkleTree::build(cid.version(), &tree, &leaves).root())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "leaf hashes do not match cid",
+            ));
+        }
+        Ok(Self {
+            inner,
+            cid,
+            tree,
+            leaves,
+            index: 0,
+            block: Vec::new(),
+            pos: 0,
+            failed: None,
+        })
+    }
+
+    pub fn from_tree(inner: R, cid: Cid, tree: &MerkleTree) -> io::Result<Self> {
+        Self::new(inner, cid, tree.leaves().to_vec())
+    }
+
+    pub fn cid(&self) -> &Cid {
+        &self.cid
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn next_block(&mut self) -> io::Result<bool> {
+        let Some(len) = self.cid.block_len(self.index) else {
+            let mut byte = [0];
+            if self.inner.read(&mut byte)? != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "trailing data after last block",
+                ));
+            }
+            return Ok(false);
+        };
+        self.block.clear();
+        self.pos = 0;
+        let mut block = mem::take(&mut self.block);
+        block.resize(len, 0);
+        self.inner.read_exact(&mut block)?;
+        if self.tree.hash_leaf(&block) != self.leaves[self.index as usize] {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("block {} does not match cid", self.index),
+            ));
+        }
+        self.block = block;
+        self.index += 1;
+        Ok(true)
+    }
+}
+impl<R: io::Read> io::Read for VerifyingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some((kind, message)) = &self.failed {
+            return Err(io::Error::new(*kind, message.clone()));
+        }
+        if self.pos == self.block.len() {
+            match self.next_block() {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(err) => {
+                    self.failed = Some((err.kind(), err.to_string()));
+                    return Err(err);
+                }
+            }
+        }
+        let n = std::cmp::min(buf.len(), self.block.len() - self.pos);
+        buf[..n].copy_from_slice(&self.block[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::BLOCK_SIZE;
+    use std::io::Read;
+
+    fn setup(len: usize) -> (Vec<u8>, Cid, MerkleTree) {
+        let data = crate::test_data(len);
+        let (cid, tree) = crate::test_tree(Cid::VERSION_RAW, &data);
+        (data, cid, tree)
+    }
+
+    #[test]
+    fn reads_verified_content() {
+        for len in [0, 1, BLOCK_SIZE, BLOCK_SIZE * 3 + 7] {
+            let (data, cid, tree) = setup(len);
+            let mut reader = VerifyingReader::from_tree(data.as_slice(), cid, &tree).unwrap();
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_block() {
+        let (mut data, cid, tree) = setup(BLOCK_SIZE * 3 + 7);
+        data[BLOCK_SIZE * 2 + 1] ^= 1;
+        let mut reader = VerifyingReader::from_tree(data.as_slice(), cid, &tree).unwrap();
+        let mut out = vec![0; BLOCK_SIZE * 2];
+        reader.read_exact(&mut out).unwrap();
+        let err = reader.read(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let again = reader.read(&mut out).unwrap_err();
+        assert_eq!(again.kind(), err.kind());
+        assert_eq!(again.to_string(), err.to_string());
+    }
+
+    #[test]
+    fn rejects_truncated_block() {
+        let (data, cid, tree) = setup(BLOCK_SIZE * 2 + 7);
+        let mut reader = VerifyingReader::from_tree(&data[..data.len() - 1], cid, &tree).unwrap();
+        let mut out = Vec::new();
+        let err = reader.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(out.len(), BLOCK_SIZE * 2);
+        assert!(reader.read(&mut [0; 16]).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_leaves() {
+        let (data, cid, tree) = setup(BLOCK_SIZE * 2);
+        let mut leaves = tree.leaves().to_vec();
+        leaves.swap(0, 1);
+        assert!(VerifyingReader::new(data.as_slice(), cid, leaves).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{
+    hasher::HashAlgorithm,
+    tree::{TreeHasher, TreeMode},
+    Cid, BLOCK_SIZE,
+};
+
+/// Describes how CIDs of a version are computed.
+///
+/// Every supported version byte has exactly one descriptor, which can be
+/// looked up with [`CidVersion::get`]. Version bytes are uppercase ASCII
+/// letters so that they can lead the textual form of a CID.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct CidVersion {
+    byte: u8,
+    name: &'static str,
+    block_size: usize,
+    hash: HashAlgorithm,
+    tree: TreeMode,
+}
+
+static VERSIONS: [CidVersion; 6] = [
+    CidVersion {
+        byte: Cid::VERSION_RAW,
+        name: "raw",
+        block_size: BLOCK_SIZE,
+        hash: HashAlgorithm::Sha256,
+        tree: TreeMode::Plain,
+    },
+    CidVersion {
+        byte: Cid::VERSION_TAGGED,
+        name: "tagged",
+        block_size: BLOCK_SIZE,
+        hash: HashAlgorithm::Sha256,
+        tree: TreeMode::Tagged,
+    },
+    CidVersion {
+        byte: Cid::VERSION_BLAKE3,
+        name: "blake3",
+        block_size: BLOCK_SIZE,
+        hash: HashAlgorithm::Blake3,
+        tree: TreeMode::Tagged,
+    },
+    CidVersion {
+        byte: Cid::VERSION_SHA512_256,
+        name: "sha512-256",
+        block_size: BLOCK_SIZE,
+        hash: HashAlgorithm::Sha512_256,
+        tree: TreeMode::Tagged,
+    },
+    CidVersion {
+        byte: Cid::VERSION_BLAKE3_256K,
+        name: "blake3-256k",
+        block_size: 256 * 1024,
+        hash: HashAlgorithm::Blake3,
+        tree: TreeMode::Tagged,
+    },
+    CidVersion {
+        byte: Cid::VERSION_BLAKE3_1M,
+        name: "blake3-1m",
+        block_size: 1024 * 1024,
+        hash: HashAlgorithm::Blake3,
+        tree: TreeMode::Tagged,
+    },
+];
+
+impl CidVersion {
+    pub fn get(byte: u8) -> Option<&'static Self> {
+        VERSIONS.iter().find(|version| version.byte == byte)
+    }
+
+    pub fn all() -> &'static [Self] {
+        &VERSIONS
+    }
+
+    pub fn byte(&self) -> u8 {
+        self.byte
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash
+    }
+
+    pub fn digest_len(&self) -> usize {
+        self.hash.digest_len()
+    }
+
+    pub fn tree_mode(&self) -> TreeMode {
+        self.tree
+    }
+
+    pub fn num_blocks(&self, size: u64) -> u64 {
+        size.div_ceil(self.block_size as u64)
+    }
+
+    /// Whether content hashed under `other` ends up with the same hash as
+    /// under this version.
+    pub fn same_scheme(&self, other: &Self) -> bool {
+        self.block_size == other.block_size && self.hash == other.hash && self.tree == other.tree
+    }
+
+    pub(crate) fn tree_hasher(&self) -> TreeHasher {
+        TreeHasher::new(self.tree, self.hash.hasher())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Hash;
+    use std::mem;
+
+    #[test]
+    fn registry_is_consistent() {
+        for (i, version) in CidVersion::all().iter().enumerate() {
+            assert!(version.byte().is_ascii_uppercase());
+            assert_eq!(CidVersion::get(version.byte()), Some(version));
+            assert_eq!(version.digest_len(), mem::size_of::<Hash>());
+            for other in &CidVersion::all()[i + 1..] {
+                assert_ne!(version.byte(), other.byte());
+                assert_ne!(version.name(), other.name());
+            }
+        }
+        assert!(CidVersion::get(b'a').is_none());
+    }
+}